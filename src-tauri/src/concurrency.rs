@@ -0,0 +1,282 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Worker semaphore whose size can be changed while a job is running.
+///
+/// Growing adds permits straight away. Shrinking forgets idle permits first; permits
+/// that are currently held by workers are reclaimed in the background as they are
+/// released, so running batches are never interrupted.
+pub struct ConcurrencyLimiter {
+    semaphore: Arc<Semaphore>,
+    limit: Mutex<usize>,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(limit: usize) -> Self {
+        let limit = limit.max(1);
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            limit: Mutex::new(limit),
+        }
+    }
+
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit, String> {
+        self.semaphore.clone().acquire_owned().await.map_err(|e| e.to_string())
+    }
+
+    pub fn limit(&self) -> usize {
+        *self.limit.lock().unwrap()
+    }
+
+    pub fn set_limit(&self, new_limit: usize) {
+        let new_limit = new_limit.max(1);
+        let mut limit = self.limit.lock().unwrap();
+        if new_limit > *limit {
+            self.semaphore.add_permits(new_limit - *limit);
+        } else if new_limit < *limit {
+            let excess = *limit - new_limit;
+            let forgotten = self.semaphore.forget_permits(excess);
+            let remaining = excess - forgotten;
+            if remaining > 0 {
+                let semaphore = self.semaphore.clone();
+                tokio::spawn(async move {
+                    if let Ok(permits) = semaphore.acquire_many_owned(remaining as u32).await {
+                        permits.forget();
+                    }
+                });
+            }
+        }
+        *limit = new_limit;
+    }
+}
+
+/// Smoothing factor of the per-line latency moving average.
+const LATENCY_ALPHA: f64 = 0.3;
+/// Latency this many times above the best average seen counts as congestion.
+const LATENCY_FACTOR: f64 = 2.0;
+/// Concurrent failures of one burst should only halve the pool once.
+const DECREASE_COOLDOWN: Duration = Duration::from_secs(10);
+/// Shorter samples are not real round trips (cached or empty answers). Taken as the
+/// best latency, they would make every later request look congested.
+const MIN_LATENCY: Duration = Duration::from_millis(1);
+
+#[derive(Default)]
+struct AimdState {
+    successes: usize,
+    latency_avg: Option<f64>,
+    latency_best: Option<f64>,
+    last_decrease: Option<Instant>,
+}
+
+/// AIMD controller on top of a [`ConcurrencyLimiter`]: one extra worker after a full
+/// round of fast successes, half the workers on 429s, timeouts or rising latency.
+pub struct AdaptiveController {
+    limiter: Arc<ConcurrencyLimiter>,
+    min: usize,
+    max: usize,
+    state: Mutex<AimdState>,
+}
+
+impl AdaptiveController {
+    pub fn new(limiter: Arc<ConcurrencyLimiter>, min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        Self {
+            limiter,
+            min,
+            max: max.max(min),
+            state: Mutex::new(AimdState::default()),
+        }
+    }
+
+    /// Records a successful request. `latency` should be normalised (e.g. per line)
+    /// so batches of different sizes are comparable. Returns the new limit.
+    pub fn on_success(&self, latency: Duration) -> usize {
+        let mut state = self.state.lock().unwrap();
+
+        if latency >= MIN_LATENCY {
+            let secs = latency.as_secs_f64();
+            let avg = match state.latency_avg {
+                Some(prev) => prev + LATENCY_ALPHA * (secs - prev),
+                None => secs,
+            };
+            state.latency_avg = Some(avg);
+            let best = state.latency_best.map_or(avg, |b| b.min(avg));
+            state.latency_best = Some(best);
+
+            if avg > best * LATENCY_FACTOR {
+                return self.decrease(&mut state);
+            }
+        }
+
+        state.successes += 1;
+        let limit = self.limiter.limit();
+        if state.successes >= limit && limit < self.max {
+            self.limiter.set_limit(limit + 1);
+            state.successes = 0;
+        }
+        self.limiter.limit()
+    }
+
+    /// Records a rate-limit or timeout error. Returns the new limit.
+    pub fn on_congestion(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        self.decrease(&mut state)
+    }
+
+    fn decrease(&self, state: &mut AimdState) -> usize {
+        let limit = self.limiter.limit();
+        if state.last_decrease.is_some_and(|t| t.elapsed() < DECREASE_COOLDOWN) {
+            return limit;
+        }
+        self.limiter.set_limit((limit / 2).max(self.min));
+        state.last_decrease = Some(Instant::now());
+        state.successes = 0;
+        // Re-measure from scratch at the new level instead of comparing against
+        // an average that was taken while overloaded.
+        state.latency_avg = None;
+        self.limiter.limit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn growing_adds_permits() {
+        let limiter = ConcurrencyLimiter::new(2);
+        limiter.set_limit(5);
+        assert_eq!(limiter.limit(), 5);
+        assert_eq!(limiter.semaphore.available_permits(), 5);
+    }
+
+    #[tokio::test]
+    async fn shrinking_idle_pool_forgets_permits() {
+        let limiter = ConcurrencyLimiter::new(4);
+        limiter.set_limit(1);
+        assert_eq!(limiter.limit(), 1);
+        assert_eq!(limiter.semaphore.available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn shrinking_reclaims_held_permits_as_they_are_released() {
+        let limiter = ConcurrencyLimiter::new(3);
+        let first = limiter.acquire().await.unwrap();
+        let second = limiter.acquire().await.unwrap();
+        let third = limiter.acquire().await.unwrap();
+
+        limiter.set_limit(1);
+        assert_eq!(limiter.limit(), 1);
+
+        // The two released permits are taken by the shrink, not handed to new workers.
+        drop(first);
+        drop(second);
+        settle().await;
+        assert_eq!(limiter.semaphore.available_permits(), 0);
+        assert!(limiter.semaphore.try_acquire().is_err());
+
+        drop(third);
+        settle().await;
+        assert_eq!(limiter.semaphore.available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn limit_never_drops_below_one() {
+        let limiter = ConcurrencyLimiter::new(0);
+        assert_eq!(limiter.limit(), 1);
+        limiter.set_limit(0);
+        assert_eq!(limiter.limit(), 1);
+        assert_eq!(limiter.semaphore.available_permits(), 1);
+    }
+
+    fn controller(limit: usize, min: usize, max: usize) -> AdaptiveController {
+        AdaptiveController::new(Arc::new(ConcurrencyLimiter::new(limit)), min, max)
+    }
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[tokio::test]
+    async fn adds_one_worker_per_round_of_successes() {
+        let aimd = controller(2, 1, 4);
+        assert_eq!(aimd.on_success(SECOND), 2);
+        assert_eq!(aimd.on_success(SECOND), 3);
+        // A round is as many successes as there are workers.
+        assert_eq!(aimd.on_success(SECOND), 3);
+        assert_eq!(aimd.on_success(SECOND), 3);
+        assert_eq!(aimd.on_success(SECOND), 4);
+        for _ in 0..10 {
+            assert_eq!(aimd.on_success(SECOND), 4);
+        }
+    }
+
+    #[tokio::test]
+    async fn congestion_halves_once_per_cooldown() {
+        let aimd = controller(8, 1, 8);
+        assert_eq!(aimd.on_congestion(), 4);
+        // The rest of the same burst of errors does not shrink it further.
+        assert_eq!(aimd.on_congestion(), 4);
+        assert_eq!(aimd.on_congestion(), 4);
+
+        aimd.state.lock().unwrap().last_decrease = Instant::now().checked_sub(DECREASE_COOLDOWN);
+        assert_eq!(aimd.on_congestion(), 2);
+    }
+
+    #[tokio::test]
+    async fn decrease_stops_at_min() {
+        let aimd = controller(3, 2, 8);
+        assert_eq!(aimd.on_congestion(), 2);
+        aimd.state.lock().unwrap().last_decrease = None;
+        assert_eq!(aimd.on_congestion(), 2);
+    }
+
+    #[tokio::test]
+    async fn rising_latency_counts_as_congestion() {
+        let aimd = controller(4, 1, 8);
+        aimd.on_success(SECOND);
+        // avg = 1 + 0.3 * (10 - 1) = 3.7, above twice the best of 1.
+        assert_eq!(aimd.on_success(SECOND * 10), 2);
+        // The average is measured afresh after a decrease.
+        assert!(aimd.state.lock().unwrap().latency_avg.is_none());
+    }
+
+    #[tokio::test]
+    async fn moderate_latency_changes_are_tolerated() {
+        let aimd = controller(4, 1, 8);
+        aimd.on_success(SECOND);
+        // avg = 1 + 0.3 * (3 - 1) = 1.6, still within twice the best.
+        assert_eq!(aimd.on_success(SECOND * 3), 4);
+    }
+
+    #[tokio::test]
+    async fn near_zero_latency_is_not_taken_as_the_best() {
+        let aimd = controller(4, 1, 8);
+        aimd.on_success(Duration::ZERO);
+        aimd.on_success(Duration::from_micros(200));
+        assert!(aimd.state.lock().unwrap().latency_best.is_none());
+
+        // Real requests afterwards are compared with each other only.
+        for _ in 0..4 {
+            aimd.on_success(SECOND);
+        }
+        assert_eq!(aimd.state.lock().unwrap().latency_best, Some(1.0));
+        assert_eq!(aimd.on_success(SECOND), 5);
+    }
+
+    #[tokio::test]
+    async fn successes_reset_after_decrease() {
+        let aimd = controller(4, 1, 8);
+        aimd.on_success(SECOND);
+        aimd.on_success(SECOND);
+        aimd.on_success(SECOND);
+        assert_eq!(aimd.on_congestion(), 2);
+        assert_eq!(aimd.on_success(SECOND), 2);
+        assert_eq!(aimd.on_success(SECOND), 3);
+    }
+}
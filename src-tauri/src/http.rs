@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Everything that goes into building the `reqwest::Client`. The client is rebuilt
+/// only when these change, so connections are reused across batches and jobs.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HttpSettings {
+    pub connect_timeout: Option<Duration>,
+    pub request_timeout: Option<Duration>,
+    pub proxy_url: String,
+    pub ca_bundle_path: String,
+    pub extra_headers: BTreeMap<String, String>,
+    pub user_agent: String,
+}
+
+#[derive(Default)]
+pub struct SharedClient {
+    current: Mutex<Option<(HttpSettings, reqwest::Client)>>,
+}
+
+impl SharedClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the cached client, rebuilding it if `settings` differ from last time.
+    pub fn get(&self, settings: HttpSettings) -> Result<reqwest::Client, String> {
+        let mut current = self.current.lock().map_err(|e| e.to_string())?;
+        if let Some((cached, client)) = current.as_ref() {
+            if *cached == settings {
+                return Ok(client.clone());
+            }
+        }
+        let client = build(&settings)?;
+        *current = Some((settings, client.clone()));
+        Ok(client)
+    }
+}
+
+fn build(settings: &HttpSettings) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder();
+
+    if let Some(t) = settings.connect_timeout {
+        builder = builder.connect_timeout(t);
+    }
+    if let Some(t) = settings.request_timeout {
+        builder = builder.timeout(t);
+    }
+
+    let proxy_url = settings.proxy_url.trim();
+    if !proxy_url.is_empty() {
+        let proxy = reqwest::Proxy::all(proxy_url).map_err(|e| format!("Invalid proxy URL: {}", e))?;
+        builder = builder.proxy(proxy);
+    }
+
+    let ca_path = settings.ca_bundle_path.trim();
+    if !ca_path.is_empty() {
+        let pem = std::fs::read(ca_path).map_err(|e| format!("Cannot read CA bundle {}: {}", ca_path, e))?;
+        let certs = reqwest::Certificate::from_pem_bundle(&pem).map_err(|e| format!("Invalid CA bundle: {}", e))?;
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    if !settings.extra_headers.is_empty() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (name, value) in &settings.extra_headers {
+            let name = reqwest::header::HeaderName::from_bytes(name.trim().as_bytes())
+                .map_err(|e| format!("Invalid header name '{}': {}", name, e))?;
+            let value = reqwest::header::HeaderValue::from_str(value.trim())
+                .map_err(|e| format!("Invalid value for header '{}': {}", name, e))?;
+            headers.insert(name, value);
+        }
+        builder = builder.default_headers(headers);
+    }
+
+    let user_agent = settings.user_agent.trim();
+    if !user_agent.is_empty() {
+        builder = builder.user_agent(user_agent);
+    }
+
+    builder.build().map_err(|e| e.to_string())
+}
@@ -0,0 +1,235 @@
+use std::path::{Path, PathBuf};
+
+/// Extensions picked up when a directory is given instead of a file.
+const TEXT_EXTENSIONS: [&str; 2] = ["txt", "csv"];
+
+/// Expands the inputs of a job into file paths, keeping their order and dropping
+/// duplicates. Each entry is a file, a directory (its text files) or a glob such as
+/// `C:\game\text\*.txt` or `data/**/*.csv`. Directories and globs skip the outputs
+/// of earlier runs, named `<stem>.<code>.<ext>` for one of `lang_codes`; a file given
+/// by name is always kept.
+pub fn expand(entries: &[String], lang_codes: &[String]) -> Result<Vec<String>, String> {
+    let mut files: Vec<String> = Vec::new();
+    for entry in entries.iter().map(|e| e.trim()).filter(|e| !e.is_empty()) {
+        let found = if is_pattern(entry) {
+            let matches: Vec<PathBuf> = glob(entry)?.into_iter().filter(|p| !is_output(p, lang_codes)).collect();
+            if matches.is_empty() {
+                return Err(format!("No files match {}", entry));
+            }
+            matches
+        } else if Path::new(entry).is_dir() {
+            let mut list: Vec<PathBuf> = read_dir(Path::new(entry))
+                .into_iter()
+                .filter(|p| is_text_file(p) && !is_output(p, lang_codes))
+                .collect();
+            list.sort();
+            list
+        } else {
+            vec![PathBuf::from(entry)]
+        };
+        for path in found {
+            let path = path.to_string_lossy().to_string();
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+    }
+    if files.is_empty() {
+        return Err("No input files".to_string());
+    }
+    Ok(files)
+}
+
+fn is_pattern(s: &str) -> bool {
+    s.contains(['*', '?', '['])
+}
+
+fn is_text_file(path: &Path) -> bool {
+    path.is_file() && path.extension().and_then(|e| e.to_str()).is_some_and(|e| TEXT_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// Whether `path` is named like a translation this tool writes (`goc.vi.txt`).
+fn is_output(path: &Path, lang_codes: &[String]) -> bool {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .and_then(|s| s.rsplit_once('.'))
+        .is_some_and(|(_, code)| lang_codes.iter().any(|c| c.eq_ignore_ascii_case(code)))
+}
+
+fn read_dir(dir: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()).collect())
+        .unwrap_or_default()
+}
+
+/// Matches a glob against the file system. `*` and `?` stay within one path
+/// component, `**` spans directories.
+fn glob(pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let normalized = pattern.replace('\\', "/");
+    // Walk from the longest leading part without wildcards.
+    let components: Vec<&str> = normalized.split('/').collect();
+    let fixed = components.iter().take_while(|c| !is_pattern(c)).count();
+    let root = match components[..fixed].join("/") {
+        r if r.is_empty() && normalized.starts_with('/') => "/".to_string(),
+        r if r.is_empty() => ".".to_string(),
+        r if r.ends_with(':') => format!("{}/", r),
+        r => r,
+    };
+    let recursive = components[fixed..].contains(&"**");
+
+    let mut regex = String::from("^");
+    let rest = components[fixed..].join("/");
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // `**/` also matches no directory at all.
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => match class(&mut chars) {
+                Some(class) => regex.push_str(&class),
+                None => regex.push_str(r"\["),
+            },
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    let re = regex::Regex::new(&regex).map_err(|e| format!("Invalid pattern {}: {}", pattern, e))?;
+
+    let root = PathBuf::from(root);
+    let mut matches = Vec::new();
+    let mut stack = vec![root.clone()];
+    let depth = components.len() - fixed;
+    while let Some(dir) = stack.pop() {
+        for path in read_dir(&dir) {
+            let Ok(relative) = path.strip_prefix(&root) else { continue };
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            if path.is_dir() {
+                if recursive || relative.split('/').count() < depth {
+                    stack.push(path);
+                }
+            } else if re.is_match(&relative) {
+                matches.push(path);
+            }
+        }
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+/// Turns the rest of a `[...]` glob class (after the `[`) into a regex class. A leading
+/// `!` negates it, as in shells. Returns `None`, consuming nothing, if it is not closed.
+fn class(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    let rest: Vec<char> = chars.clone().collect();
+    let negated = matches!(rest.first(), Some('!' | '^'));
+    let start = usize::from(negated);
+    // A `]` right after the opening bracket is part of the class.
+    let end = (start + 1..rest.len()).find(|i| rest[*i] == ']')?;
+    // Like `*` and `?`, a negated class never matches a path separator.
+    let mut class = String::from(if negated { "[^/" } else { "[" });
+    for c in &rest[start..end] {
+        // Regex escapes and set operators have no meaning in a glob.
+        if matches!(c, '\\' | '[' | ']' | '&' | '~') {
+            class.push('\\');
+        }
+        class.push(*c);
+    }
+    class.push(']');
+    chars.nth(end);
+    Some(class)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory holding `files`, given relative to it.
+    fn tree(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("inputs-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        for file in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "1:::a").unwrap();
+        }
+        dir
+    }
+
+    fn names(dir: &Path, pattern: &str) -> Vec<String> {
+        let pattern = format!("{}/{}", dir.to_string_lossy().replace('\\', "/"), pattern);
+        let files = glob(&pattern).unwrap();
+        files.iter().map(|p| p.strip_prefix(dir).unwrap().to_string_lossy().replace('\\', "/")).collect()
+    }
+
+    #[test]
+    fn star_stays_within_one_directory() {
+        let dir = tree("star", &["a.txt", "b.csv", "sub/c.txt"]);
+        assert_eq!(names(&dir, "*.txt"), ["a.txt"]);
+        assert_eq!(names(&dir, "*/*.txt"), ["sub/c.txt"]);
+    }
+
+    #[test]
+    fn double_star_spans_directories() {
+        let dir = tree("double", &["a.txt", "sub/c.txt", "sub/deep/d.txt", "sub/e.csv"]);
+        assert_eq!(names(&dir, "**/*.txt"), ["a.txt", "sub/c.txt", "sub/deep/d.txt"]);
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        let dir = tree("question", &["a1.txt", "a12.txt"]);
+        assert_eq!(names(&dir, "a?.txt"), ["a1.txt"]);
+    }
+
+    #[test]
+    fn classes_and_their_negation() {
+        let dir = tree("class", &["a.txt", "b.txt", "c.txt", "!.txt"]);
+        assert_eq!(names(&dir, "[a-b].txt"), ["a.txt", "b.txt"]);
+        assert_eq!(names(&dir, "[!ab].txt"), ["!.txt", "c.txt"]);
+        assert_eq!(names(&dir, "[^ab].txt"), ["!.txt", "c.txt"]);
+    }
+
+    #[test]
+    fn unclosed_bracket_is_literal() {
+        let dir = tree("bracket", &["[a.txt", "a.txt"]);
+        assert_eq!(names(&dir, "*[a.txt"), ["[a.txt"]);
+    }
+
+    #[test]
+    fn directories_give_their_text_files_and_duplicates_are_dropped() {
+        let dir = tree("expand", &["b.txt", "a.csv", "c.json"]);
+        let root = dir.to_string_lossy().to_string();
+        let a = dir.join("a.csv").to_string_lossy().to_string();
+        let b = dir.join("b.txt").to_string_lossy().to_string();
+        let files = expand(&[b.clone(), root, " ".to_string()], &[]).unwrap();
+        assert_eq!(files, [b, a]);
+    }
+
+    #[test]
+    fn outputs_of_earlier_runs_are_skipped() {
+        let dir = tree("outputs", &["goc.txt", "goc.vi.txt", "goc.id.txt", "v1.2.txt", "sub/x.csv", "sub/x.vi.csv"]);
+        let codes = ["vi".to_string(), "id".to_string()];
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+
+        let files = expand(&[dir.to_string_lossy().to_string()], &codes).unwrap();
+        assert_eq!(files, [path("goc.txt"), path("v1.2.txt")]);
+        let files = expand(&[format!("{}/**/*.*", dir.display())], &codes).unwrap();
+        assert_eq!(files, [path("goc.txt"), path("sub/x.csv"), path("v1.2.txt")]);
+        // Named explicitly, an output is translated like any other file.
+        assert_eq!(expand(&[path("goc.vi.txt")], &codes).unwrap(), [path("goc.vi.txt")]);
+    }
+
+    #[test]
+    fn empty_matches_are_errors() {
+        let dir = tree("empty", &["a.txt"]);
+        assert!(expand(&[format!("{}/*.csv", dir.display())], &[]).unwrap_err().starts_with("No files match"));
+        assert_eq!(expand(&["".to_string()], &[]).unwrap_err(), "No input files");
+    }
+}
@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::quality::LineQuality;
+
+/// A translation of an ID produced by a batch that does not own it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Alternate {
+    /// `start-end` IDs of the batch that produced it.
+    pub batch: String,
+    pub text: String,
+}
+
+/// An ID that got more than one translation. `chosen` is what was written to the
+/// output: always the translation of the batch whose core range holds the ID.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Conflict {
+    pub chosen: String,
+    pub alternates: Vec<Alternate>,
+}
+
+/// A line the review pass changed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Review {
+    pub draft: String,
+    pub reviewed: String,
+}
+
+/// One input file of a job, translated into one of its languages.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct JobFile {
+    pub source_file: String,
+    pub output_file: String,
+    /// Index into the job's target languages.
+    #[serde(default)]
+    pub target: usize,
+    #[serde(default)]
+    pub target_lang: String,
+    /// IDs that got more than one translation, by ID.
+    #[serde(default)]
+    pub conflicts: BTreeMap<String, Conflict>,
+    /// Draft and corrected text of the IDs the reviewer changed, by ID.
+    #[serde(default)]
+    pub reviews: BTreeMap<String, Review>,
+    /// Automatic quality checks of the lines that have issues, by ID.
+    #[serde(default)]
+    pub quality: BTreeMap<String, LineQuality>,
+}
+
+impl JobFile {
+    pub fn new(source_file: &str, output_file: &str) -> Self {
+        Self { source_file: source_file.to_string(), output_file: output_file.to_string(), ..Default::default() }
+    }
+
+    pub fn add_alternate(&mut self, id: &str, batch: &str, text: &str) {
+        let conflict = self.conflicts.entry(id.to_string()).or_default();
+        let alternate = Alternate { batch: batch.to_string(), text: text.to_string() };
+        // Retries of the same batch tend to repeat themselves.
+        if !conflict.alternates.contains(&alternate) {
+            conflict.alternates.push(alternate);
+        }
+    }
+
+    pub fn add_review(&mut self, id: &str, draft: &str, reviewed: &str) {
+        self.reviews.insert(id.to_string(), Review { draft: draft.to_string(), reviewed: reviewed.to_string() });
+    }
+
+    /// Keeps `quality` if it found issues; a clean re-translation clears the old entry.
+    pub fn set_quality(&mut self, id: &str, quality: LineQuality) {
+        if quality.issues.is_empty() {
+            self.quality.remove(id);
+        } else {
+            self.quality.insert(id.to_string(), quality);
+        }
+    }
+
+    /// Fills in `chosen` from the final output lines of this file.
+    pub fn resolve(&mut self, output: &[String]) {
+        let by_id: HashMap<&str, &str> = output
+            .iter()
+            .filter_map(|l| l.split_once(":::"))
+            .map(|(id, text)| (id.trim(), text))
+            .collect();
+        for (id, conflict) in self.conflicts.iter_mut() {
+            conflict.chosen = by_id.get(id.as_str()).unwrap_or(&"").to_string();
+        }
+    }
+}
+
+/// Review data of one translation job, saved as `job.json` in the job's working directory.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct JobRecord {
+    pub job_id: String,
+    pub started_at: String,
+    /// Set once the output files have been written; unfinished jobs can be resumed.
+    #[serde(default)]
+    pub finished: bool,
+    pub files: Vec<JobFile>,
+}
+
+impl JobRecord {
+    pub fn new(job_id: &str, files: Vec<JobFile>) -> Self {
+        Self {
+            job_id: job_id.to_string(),
+            started_at: chrono::Local::now().to_rfc3339(),
+            files,
+            ..Default::default()
+        }
+    }
+
+    pub fn conflict_count(&self) -> usize {
+        self.files.iter().map(|f| f.conflicts.len()).sum()
+    }
+
+    pub fn review_count(&self) -> usize {
+        self.files.iter().map(|f| f.reviews.len()).sum()
+    }
+
+    pub fn load(work_dir: &Path) -> Result<Self, String> {
+        let path = work_dir.join("job.json");
+        let content = std::fs::read_to_string(&path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, work_dir: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        crate::storage::write_atomic(&work_dir.join("job.json"), json.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternates_are_deduplicated_and_resolved_from_the_output() {
+        let mut file = JobFile::new("goc.txt", "goc.vi.txt");
+        file.add_alternate("12", "1-10", "bản khác");
+        file.add_alternate("12", "1-10", "bản khác");
+        file.add_alternate("12", "20-30", "bản thứ ba");
+        assert_eq!(file.conflicts["12"].alternates.len(), 2);
+
+        file.resolve(&["0:::header".to_string(), "12:::bản chính".to_string()]);
+        assert_eq!(file.conflicts["12"].chosen, "bản chính");
+    }
+
+
+    #[test]
+    fn clean_quality_clears_the_old_entry() {
+        let mut file = JobFile::new("goc.txt", "goc.vi.txt");
+        file.set_quality("1", LineQuality { score: 60, issues: vec!["length ratio 4.00".to_string()], ..Default::default() });
+        assert!(file.quality.contains_key("1"));
+        file.set_quality("1", LineQuality { score: 100, ..Default::default() });
+        assert!(file.quality.is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// One translated line.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct JournalEntry {
+    /// Index of the source file within the job.
+    #[serde(default)]
+    pub file: usize,
+    /// Index of the line in the source file.
+    pub line: usize,
+    pub id: String,
+    pub text: String,
+}
+
+impl JournalEntry {
+    /// Splits an `ID:::Text` output line. Lines without an ID are not journaled.
+    pub fn from_output(file: usize, line: usize, output: &str) -> Option<Self> {
+        let (id, text) = output.split_once(":::")?;
+        Some(Self { file, line, id: id.trim().to_string(), text: text.to_string() })
+    }
+
+    pub fn to_output(&self) -> String {
+        format!("{}:::{}", self.id, self.text)
+    }
+}
+
+/// Append-only JSONL log of finished lines. Each batch costs one small append instead
+/// of rewriting the whole output, and the log can be replayed to resume a job.
+pub struct Journal {
+    file: Mutex<File>,
+}
+
+impl Journal {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Cannot open journal {}: {}", path.display(), e))?;
+        // Terminate a torn last record so the next append starts on its own line.
+        let ends_with_newline = std::fs::read(path).map(|b| b.last().is_none_or(|c| *c == b'\n')).unwrap_or(true);
+        if !ends_with_newline {
+            file.write_all(b"\n").map_err(|e| e.to_string())?;
+        }
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    /// Appends the entries of one batch with a single write.
+    pub fn append(&self, entries: &[JournalEntry]) -> Result<(), String> {
+        let mut buf = String::new();
+        for entry in entries {
+            buf.push_str(&serde_json::to_string(entry).map_err(|e| e.to_string())?);
+            buf.push('\n');
+        }
+        let mut file = self.file.lock().map_err(|e| e.to_string())?;
+        file.write_all(buf.as_bytes()).map_err(|e| e.to_string())?;
+        file.sync_data().map_err(|e| e.to_string())
+    }
+}
+
+/// Latest entry per `(file, line)`. A torn last record from a crash is skipped.
+pub fn replay(path: &Path) -> BTreeMap<(usize, usize), JournalEntry> {
+    std::fs::read_to_string(path)
+        .map(|content| {
+            content
+                .lines()
+                .filter_map(|l| serde_json::from_str::<JournalEntry>(l).ok())
+                .map(|e| ((e.file, e.line), e))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_journal(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("journal-test-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("journal.jsonl");
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn entry(file: usize, line: usize, id: &str, text: &str) -> JournalEntry {
+        JournalEntry { file, line, id: id.to_string(), text: text.to_string() }
+    }
+
+    #[test]
+    fn splits_output_lines() {
+        assert_eq!(JournalEntry::from_output(1, 4, " 12 :::Xin chào"), Some(entry(1, 4, "12", "Xin chào")));
+        assert_eq!(JournalEntry::from_output(0, 0, "no id here"), None);
+        assert_eq!(entry(0, 0, "7", "a:::b").to_output(), "7:::a:::b");
+    }
+
+    #[test]
+    fn replays_latest_entry_per_line() {
+        let path = temp_journal("latest");
+        let journal = Journal::open(&path).unwrap();
+        journal.append(&[entry(0, 1, "1", "first"), entry(0, 2, "2", "two")]).unwrap();
+        journal.append(&[entry(0, 1, "1", "second"), entry(1, 1, "1", "other file")]).unwrap();
+
+        let replayed = replay(&path);
+        assert_eq!(replayed.len(), 3);
+        assert_eq!(replayed[&(0, 1)].text, "second");
+        assert_eq!(replayed[&(1, 1)].text, "other file");
+    }
+
+    #[test]
+    fn skips_a_torn_last_record() {
+        let path = temp_journal("torn");
+        std::fs::write(&path, "{\"file\":0,\"line\":1,\"id\":\"1\",\"text\":\"ok\"}\n{\"file\":0,\"line\":2,\"id\":\"2\",\"te").unwrap();
+        let replayed = replay(&path);
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(replayed[&(0, 1)].text, "ok");
+    }
+
+    #[test]
+    fn appends_after_a_torn_record_start_on_a_new_line() {
+        let path = temp_journal("reopen");
+        std::fs::write(&path, "{\"file\":0,\"line\":1,\"id\":\"1\",\"text\":\"ok\"}\n{\"line\":2,\"id\"").unwrap();
+        Journal::open(&path).unwrap().append(&[entry(0, 3, "3", "after crash")]).unwrap();
+
+        let replayed = replay(&path);
+        assert_eq!(replayed.len(), 2);
+        assert_eq!(replayed[&(0, 3)].text, "after crash");
+    }
+
+    #[test]
+    fn entries_without_file_belong_to_the_first() {
+        let path = temp_journal("legacy");
+        std::fs::write(&path, "{\"line\":5,\"id\":\"5\",\"text\":\"old format\"}\n").unwrap();
+        assert_eq!(replay(&path)[&(0, 5)].text, "old format");
+    }
+
+    #[test]
+    fn missing_journal_replays_nothing() {
+        assert!(replay(&temp_journal("missing")).is_empty());
+    }
+}
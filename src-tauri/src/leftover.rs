@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// What happens to lines that still look untranslated after their batch.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LeftoverAction {
+    /// Noted as a quality issue of the line.
+    #[default]
+    Flag,
+    /// Sent once more in a batch of their own; flagged if still untranslated.
+    Requeue,
+}
+
+/// Consecutive source words in a translation that count as an untranslated phrase.
+const MIN_RUN: usize = 3;
+
+fn english_words() -> &'static HashSet<&'static str> {
+    static WORDS: OnceLock<HashSet<&'static str>> = OnceLock::new();
+    WORDS.get_or_init(|| {
+        include_str!("wordlists/english.txt")
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .collect()
+    })
+}
+
+fn words(text: &str) -> Vec<&str> {
+    static RE_WORD: OnceLock<regex::Regex> = OnceLock::new();
+    let re = RE_WORD.get_or_init(|| regex::Regex::new(r"\p{L}+(?:'\p{L}+)?").unwrap());
+    re.find_iter(text).map(|m| m.as_str()).collect()
+}
+
+fn is_kana(c: char) -> bool {
+    ('\u{3040}'..='\u{30FF}').contains(&c)
+}
+
+fn is_han(c: char) -> bool {
+    ('\u{3400}'..='\u{4DBF}').contains(&c) || ('\u{4E00}'..='\u{9FFF}').contains(&c)
+}
+
+fn is_hangul(c: char) -> bool {
+    ('\u{AC00}'..='\u{D7AF}').contains(&c)
+}
+
+/// Why a translated line looks partly or fully untranslated, if it does. Contents are
+/// passed without the `ID:::` prefix.
+pub fn detect(source: &str, translation: &str, source_lang: &str, target_lang: &str) -> Option<String> {
+    let target = target_lang.trim().to_lowercase();
+    let cjk_target = matches!(target.as_str(), "japanese" | "chinese" | "simplified chinese" | "traditional chinese" | "korean");
+
+    if source_lang.trim().eq_ignore_ascii_case("english") {
+        if let Some(phrase) = english_leftover(source, translation) {
+            return Some(format!("untranslated English: \"{}\"", phrase));
+        }
+        // Vietnamese marks nearly every word; a long line without any is still English.
+        let tokens = words(translation);
+        let lowercase = tokens.iter().filter(|w| w.starts_with(|c: char| c.is_lowercase())).count();
+        if target == "vietnamese" && tokens.len() >= 5 && lowercase * 2 >= tokens.len() && tokens.iter().all(|w| w.is_ascii()) {
+            return Some("no Vietnamese diacritics".to_string());
+        }
+    }
+
+    if cjk_target {
+        let chars: Vec<char> = translation.chars().collect();
+        let han = chars.iter().filter(|c| is_han(**c)).count();
+        let kana = chars.iter().filter(|c| is_kana(**c)).count();
+        let hangul = chars.iter().filter(|c| is_hangul(**c)).count();
+        let latin = chars.iter().filter(|c| c.is_ascii_alphabetic()).count();
+        let reason = match target.as_str() {
+            // Japanese sentences almost always have kana; pure Han is Chinese left as is.
+            "japanese" if han >= 6 && kana == 0 => Some("Han characters without kana"),
+            "korean" if han + kana > hangul => Some("more Han/kana than Hangul"),
+            "chinese" | "simplified chinese" | "traditional chinese" if kana + hangul > 0 => Some("kana or Hangul in Chinese"),
+            _ if latin > 0 && latin * 2 > han + kana + hangul + latin && words(translation).len() >= MIN_RUN => {
+                Some("mostly Latin letters")
+            }
+            _ => None,
+        };
+        return reason.map(|r| r.to_string());
+    }
+    None
+}
+
+/// Longest run of lowercase common English words that also occur in the source,
+/// or a translation made mostly of them.
+fn english_leftover(source: &str, translation: &str) -> Option<String> {
+    let dictionary = english_words();
+    let source_words: HashSet<String> = words(source).iter().map(|w| w.to_lowercase()).collect();
+    let tokens = words(translation);
+    // Capitalized words are usually names, which are kept on purpose.
+    let leftover = |w: &str| {
+        w.is_ascii()
+            && w.len() >= 2
+            && w.starts_with(|c: char| c.is_ascii_lowercase())
+            && dictionary.contains(w)
+            && source_words.contains(w)
+    };
+
+    let (mut best, mut current) = (0..0, 0..0);
+    for (i, w) in tokens.iter().enumerate() {
+        if leftover(w) {
+            if current.is_empty() {
+                current = i..i;
+            }
+            current.end = i + 1;
+            if current.len() > best.len() {
+                best = current.clone();
+            }
+        } else {
+            current = 0..0;
+        }
+    }
+    let count = tokens.iter().filter(|w| leftover(w)).count();
+    if best.len() >= MIN_RUN || (count >= 2 && count * 2 >= tokens.len()) {
+        Some(tokens[best].join(" "))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vi(source: &str, translation: &str) -> Option<String> {
+        detect(source, translation, "English", "Vietnamese")
+    }
+
+    #[test]
+    fn english_phrases_copied_from_the_source_are_found() {
+        assert_eq!(
+            vi("Open the door and look inside", "Hãy open the door and nhìn vào"),
+            Some("untranslated English: \"open the door and\"".to_string())
+        );
+        assert_eq!(vi("Open the door", "Mở cửa ra"), None);
+    }
+
+    #[test]
+    fn names_are_not_leftovers() {
+        assert_eq!(vi("Talk to Rover about the Echo", "Nói chuyện với Rover về Echo"), None);
+    }
+
+    #[test]
+    fn long_lines_without_diacritics_are_flagged() {
+        assert_eq!(vi("Hello there", "chao ban toi la nguoi moi"), Some("no Vietnamese diacritics".to_string()));
+        // Short lines may be names or codes.
+        assert_eq!(vi("Hello", "chao ban"), None);
+    }
+
+    #[test]
+    fn cjk_targets_check_the_script() {
+        assert_eq!(detect("你好", "今天天气很好我们出去", "Chinese", "Japanese"), Some("Han characters without kana".to_string()));
+        assert_eq!(detect("你好", "今日は天気がいいですね", "Chinese", "Japanese"), None);
+        assert_eq!(detect("Hello", "こんにちは世界", "English", "Chinese"), Some("kana or Hangul in Chinese".to_string()));
+        assert_eq!(detect("Hello", "안녕하세요", "English", "Korean"), None);
+        assert_eq!(detect("Go", "We should go now", "English", "Korean"), Some("mostly Latin letters".to_string()));
+    }
+}
@@ -1,4 +1,20 @@
+mod concurrency;
+mod http;
+mod inputs;
+mod job;
+mod journal;
+mod leftover;
+mod memory;
+mod patch;
+mod prompt;
+mod protocol;
+mod quality;
+mod queue;
+mod segment;
+mod sse;
+mod storage;
 mod translator;
+mod usage;
 
 use translator::TranslatorState;
 use tauri::Manager; // Import Manager trait for get_webview_window
@@ -23,9 +39,22 @@ pub fn run() {
             greet,
             translator::start_translation,
             translator::stop_translation,
+            translator::pause_translation,
+            translator::resume_translation,
+            translator::adjust_translation,
             translator::fetch_models,
+            translator::list_prompt_templates,
+            translator::list_prompt_presets,
             translator::load_config,
-            translator::save_config
+            translator::save_config,
+            translator::get_usage_summary,
+            translator::list_jobs,
+            translator::enqueue_job,
+            translator::list_queue,
+            translator::remove_queued_job,
+            translator::run_queue,
+            translator::prepare_patch_job,
+            translator::export_review_queue
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Exact-match translation memory shared by all workers and files of a job. A source
+/// text translated once is reused for its repeats instead of being sent again.
+#[derive(Default)]
+pub struct TranslationMemory {
+    entries: Mutex<HashMap<(String, String), String>>,
+}
+
+impl TranslationMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, target_lang: &str, source: &str) -> Option<String> {
+        let source = source.trim();
+        if source.is_empty() {
+            return None;
+        }
+        self.entries.lock().unwrap().get(&(target_lang.to_string(), source.to_string())).cloned()
+    }
+
+    /// Ignores empty texts and "translations" equal to the source, which is what a line
+    /// the model skipped falls back to.
+    pub fn insert(&self, target_lang: &str, source: &str, translation: &str) {
+        let (source, translation) = (source.trim(), translation.trim());
+        if source.is_empty() || translation.is_empty() || source == translation {
+            return;
+        }
+        self.entries
+            .lock()
+            .unwrap()
+            .insert((target_lang.to_string(), source.to_string()), translation.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn languages_are_kept_apart() {
+        let memory = TranslationMemory::new();
+        memory.insert("Vietnamese", "Hello", "Xin chào");
+        memory.insert("French", "Hello", "Bonjour");
+        assert_eq!(memory.get("Vietnamese", " Hello ").as_deref(), Some("Xin chào"));
+        assert_eq!(memory.get("French", "Hello").as_deref(), Some("Bonjour"));
+        assert_eq!(memory.get("German", "Hello"), None);
+    }
+
+    #[test]
+    fn skipped_and_empty_lines_are_not_remembered() {
+        let memory = TranslationMemory::new();
+        memory.insert("Vietnamese", "Hello", " Hello ");
+        memory.insert("Vietnamese", "Bye", "");
+        memory.insert("Vietnamese", "  ", "Trống");
+        assert_eq!(memory.get("Vietnamese", "Hello"), None);
+        assert_eq!(memory.get("Vietnamese", "Bye"), None);
+        assert_eq!(memory.get("Vietnamese", "  "), None);
+        assert_eq!(memory.get("Vietnamese", ""), None);
+    }
+}
@@ -0,0 +1,123 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// What changed in a source file between two game patches, by ID.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct PatchReport {
+    pub job_id: String,
+    pub old_source: String,
+    pub old_translation: String,
+    pub new_source: String,
+    pub output_file: String,
+    /// Same text as before; the old translation is carried over.
+    pub unchanged: usize,
+    pub modified: Vec<String>,
+    pub new: Vec<String>,
+    pub removed: Vec<String>,
+    /// Unchanged IDs the old translation has no text for. Translated like new ones.
+    pub untranslated: Vec<String>,
+}
+
+pub struct PatchDiff {
+    /// `(line in the new source, ID, old translation)` of the lines carried over.
+    pub carried: Vec<(usize, String, String)>,
+    pub report: PatchReport,
+}
+
+/// Compares the old and new source by ID. Lines without an ID and the `0:::` header
+/// are ignored; for a duplicated ID the first occurrence counts.
+pub fn diff(old_source: &[String], old_translation: &[String], new_source: &[String]) -> PatchDiff {
+    let old = by_id(old_source);
+    let translated = by_id(old_translation);
+    let mut seen = HashSet::new();
+    let mut carried = Vec::new();
+    let mut report = PatchReport::default();
+
+    for (line, text) in new_source.iter().enumerate() {
+        let Some((id, content)) = text.split_once(":::") else { continue };
+        let id = id.trim();
+        if (line == 0 && text.starts_with("0:::")) || !seen.insert(id) {
+            continue;
+        }
+        match old.get(id) {
+            None => report.new.push(id.to_string()),
+            Some(old_content) if old_content.trim() != content.trim() => report.modified.push(id.to_string()),
+            Some(_) => match translated.get(id).filter(|t| !t.trim().is_empty() || content.trim().is_empty()) {
+                Some(translation) => {
+                    report.unchanged += 1;
+                    carried.push((line, id.to_string(), translation.to_string()));
+                }
+                None => report.untranslated.push(id.to_string()),
+            },
+        }
+    }
+    let mut removed = HashSet::new();
+    report.removed = old_source
+        .iter()
+        .filter_map(|l| l.split_once(":::").map(|(id, _)| id.trim()))
+        .filter(|id| *id != "0" && !seen.contains(id) && removed.insert(*id))
+        .map(|id| id.to_string())
+        .collect();
+
+    PatchDiff { carried, report }
+}
+
+fn by_id(lines: &[String]) -> HashMap<&str, &str> {
+    let mut map = HashMap::new();
+    for (id, content) in lines.iter().filter_map(|l| l.split_once(":::")) {
+        map.entry(id.trim()).or_insert(content);
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &[&str]) -> Vec<String> {
+        text.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn classifies_lines_by_id() {
+        let old = lines(&["0:::header", "1:::Hello", "2:::Attack", "3:::Gone"]);
+        let translation = lines(&["0:::header", "1:::Xin chào", "2:::Tấn công", "3:::Mất"]);
+        let new = lines(&["0:::header v2", "1:::Hello", "2:::Heavy Attack", "4:::New line"]);
+        let PatchDiff { carried, report } = diff(&old, &translation, &new);
+        assert_eq!(report.unchanged, 1);
+        assert_eq!(carried, vec![(1, "1".to_string(), "Xin chào".to_string())]);
+        assert_eq!(report.modified, ["2"]);
+        assert_eq!(report.new, ["4"]);
+        assert_eq!(report.removed, ["3"]);
+        assert!(report.untranslated.is_empty());
+    }
+
+    #[test]
+    fn unchanged_lines_without_a_translation_are_sent_again() {
+        let old = lines(&["1:::Hello", "2:::", "3:::Bye"]);
+        let translation = lines(&["1:::", "2:::"]);
+        let new = lines(&["1:::Hello", "2:::", "3:::Bye"]);
+        let PatchDiff { carried, report } = diff(&old, &translation, &new);
+        // An empty source line keeps its empty translation.
+        assert_eq!(carried, vec![(1, "2".to_string(), String::new())]);
+        assert_eq!(report.untranslated, ["1", "3"]);
+    }
+
+    #[test]
+    fn whitespace_changes_are_not_modifications() {
+        let PatchDiff { report, .. } = diff(&lines(&["1:::Hello "]), &lines(&["1:::Xin chào"]), &lines(&[" 1 ::: Hello"]));
+        assert_eq!(report.unchanged, 1);
+        assert!(report.modified.is_empty());
+    }
+
+    #[test]
+    fn first_occurrence_of_a_duplicated_id_counts() {
+        let old = lines(&["1:::Hello", "1:::Other", "2:::Bye", "2:::Bye"]);
+        let translation = lines(&["1:::Xin chào", "1:::Khác"]);
+        let new = lines(&["1:::Hello", "1:::Changed", "no id here"]);
+        let PatchDiff { carried, report } = diff(&old, &translation, &new);
+        assert_eq!(carried, vec![(0, "1".to_string(), "Xin chào".to_string())]);
+        assert!(report.modified.is_empty());
+        assert_eq!(report.removed, ["2"]);
+    }
+}
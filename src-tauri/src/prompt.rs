@@ -0,0 +1,348 @@
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// User message used when no user template is selected: the reference-only context
+/// (if any), the batch lines and the format reminder.
+pub const DEFAULT_USER_TEMPLATE: &str = "{{context}}{{batch}}\n\n{{format_reminder}}";
+
+/// System prompt of the review pass when no review template is selected.
+pub const DEFAULT_REVIEW_SYSTEM: &str = "You are reviewing a {{source_lang}} to {{target_lang}} game translation. \
+For each ID, compare the draft with the source. If the draft is accurate and natural, reply `ID:::OK`. \
+Otherwise reply `ID:::` followed by the corrected translation. Keep placeholders, tags and `\\n` exactly as in the source.";
+
+/// User message of the review pass: the source lines, then the drafts (`{{batch}}`).
+pub const REVIEW_USER_TEMPLATE: &str = "Source:\n{{source}}\n\nDraft translation:\n{{batch}}\n\n{{format_reminder}}";
+
+fn var_regex() -> &'static regex::Regex {
+    static RE_VAR: OnceLock<regex::Regex> = OnceLock::new();
+    RE_VAR.get_or_init(|| regex::Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap())
+}
+
+/// Replaces `{{name}}` (whitespace inside the braces allowed) with the value of `name`.
+/// Unknown variables are left untouched so typos stay visible in the request log.
+pub fn render(template: &str, vars: &HashMap<&str, String>) -> String {
+    var_regex()
+        .replace_all(template, |caps: &regex::Captures| match vars.get(&caps[1]) {
+            Some(value) => value.clone(),
+            None => caps[0].to_string(),
+        })
+        .into_owned()
+}
+
+/// Whether `template` uses the variable `name`.
+pub fn uses_var(template: &str, name: &str) -> bool {
+    var_regex().captures_iter(template).any(|caps| &caps[1] == name)
+}
+
+/// The `{{context}}` block: neighbouring lines shown without their IDs so the model
+/// has nothing to answer for them. Empty when there is no context.
+pub fn context_block(before: &str, after: &str) -> String {
+    if before.is_empty() && after.is_empty() {
+        return String::new();
+    }
+    let mut block = String::from("REFERENCE ONLY - surrounding lines for context. Do NOT translate or output them.\n");
+    if !before.is_empty() {
+        block.push_str("Before:\n");
+        block.push_str(before);
+        block.push('\n');
+    }
+    if !after.is_empty() {
+        block.push_str("After:\n");
+        block.push_str(after);
+        block.push('\n');
+    }
+    block.push_str("\nLines to translate:\n");
+    block
+}
+
+/// Path of template `name` in the first of `dirs` that has it; the first dir if none
+/// does. `name` may omit the `.md` extension.
+fn template_path(dirs: &[PathBuf], name: &str) -> PathBuf {
+    let candidates = |dir: &PathBuf| {
+        let path = dir.join(name);
+        let with_ext = (path.extension().is_none()).then(|| path.with_extension("md"));
+        [Some(path), with_ext].into_iter().flatten()
+    };
+    dirs.iter()
+        .flat_map(candidates)
+        .find(|p| p.is_file())
+        .unwrap_or_else(|| dirs.first().map(|d| d.join(name)).unwrap_or_else(|| PathBuf::from(name)))
+}
+
+/// Reads a template file from the prompts directories, without its front matter.
+/// `dirs` are searched in order, so an earlier one overrides a later one. `name` may
+/// omit the `.md` extension and must be a plain file name.
+pub fn read_template(dirs: &[PathBuf], name: &str) -> Result<String, String> {
+    if !crate::storage::is_plain_name(name) {
+        return Err(format!("Prompt template name {} must be a file in the prompts directory", name));
+    }
+    let path = template_path(dirs, name);
+    let content = std::fs::read_to_string(&path).map_err(|e| format!("Cannot read prompt template {}: {}", path.display(), e))?;
+    Ok(split_front_matter(&content).1.to_string())
+}
+
+/// Splits a leading `---` block of `key: value` lines off `content`.
+pub fn split_front_matter(content: &str) -> (HashMap<String, String>, &str) {
+    let mut meta = HashMap::new();
+    let Some(rest) = content.strip_prefix("---\n").or_else(|| content.strip_prefix("---\r\n")) else {
+        return (meta, content);
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        let line = line.trim();
+        if line == "---" {
+            return (meta, rest[offset..].trim_start_matches(['\r', '\n']));
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            let value = value.trim().trim_matches('"').trim_matches('\'');
+            meta.insert(key.trim().to_lowercase(), value.to_string());
+        }
+    }
+    // No closing fence: not front matter after all.
+    (HashMap::new(), content)
+}
+
+/// A prompt file in the prompts directory together with its front-matter metadata.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct PromptPreset {
+    /// `name` from the front matter, or the file stem.
+    pub name: String,
+    pub file: String,
+    pub game: Option<String>,
+    pub source_lang: Option<String>,
+    pub target_lang: Option<String>,
+    /// Recommended model and temperature, applied by the UI when the preset is picked.
+    pub model: Option<String>,
+    pub temperature: Option<f64>,
+    pub description: Option<String>,
+}
+
+pub fn list_presets(dirs: &[PathBuf]) -> Vec<PromptPreset> {
+    list_templates(dirs)
+        .into_iter()
+        .filter_map(|file| {
+            let content = std::fs::read_to_string(template_path(dirs, &file)).ok()?;
+            let (meta, _) = split_front_matter(&content);
+            let stem = Path::new(&file).file_stem().and_then(|s| s.to_str()).unwrap_or(&file).to_string();
+            Some(PromptPreset {
+                name: meta.get("name").cloned().unwrap_or(stem),
+                game: meta.get("game").cloned(),
+                source_lang: meta.get("source_lang").cloned(),
+                target_lang: meta.get("target_lang").cloned(),
+                model: meta.get("model").cloned(),
+                temperature: meta.get("temperature").and_then(|t| t.parse().ok()),
+                description: meta.get("description").cloned(),
+                file,
+            })
+        })
+        .collect()
+}
+
+/// Finds a preset by its name or file name.
+pub fn find_preset(dirs: &[PathBuf], name: &str) -> Result<(PromptPreset, String), String> {
+    let preset = list_presets(dirs)
+        .into_iter()
+        .find(|p| p.name == name || p.file == name)
+        .ok_or_else(|| {
+            let dirs: Vec<String> = dirs.iter().map(|d| d.display().to_string()).collect();
+            format!("Prompt preset '{}' not found in {}", name, dirs.join(", "))
+        })?;
+    let body = read_template(dirs, &preset.file)?;
+    Ok((preset, body))
+}
+
+/// Lists the template files (`.md`, `.txt`) in the prompts directories, each name once.
+pub fn list_templates(dirs: &[PathBuf]) -> Vec<String> {
+    let mut names: Vec<String> = dirs
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()))
+        .filter(|p| p.is_file() && matches!(p.extension().and_then(|x| x.to_str()), Some("md") | Some("txt")))
+        .filter_map(|p| p.file_name().and_then(|n| n.to_str()).map(|n| n.to_string()))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Term list injected as `{{glossary}}`. One entry per line, `term:::translation`
+/// (a tab also works as separator); empty lines and `#` comments are skipped.
+#[derive(Debug, Clone, Default)]
+pub struct Glossary {
+    entries: Vec<(String, String)>,
+}
+
+impl Glossary {
+    pub fn load(path: &str) -> Result<Self, String> {
+        if path.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path.trim()).map_err(|e| format!("Cannot read glossary {}: {}", path, e))?;
+        Ok(Self::parse(&content))
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let entries = content
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .filter_map(|l| l.split_once(":::").or_else(|| l.split_once('\t')))
+            .map(|(term, translation)| (term.trim().to_string(), translation.trim().to_string()))
+            .filter(|(term, _)| !term.is_empty())
+            .collect();
+        Self { entries }
+    }
+
+    /// Entries whose term occurs in `text` (case-insensitive).
+    pub fn matching(&self, text: &str) -> Vec<(&str, &str)> {
+        let haystack = text.to_lowercase();
+        self.entries
+            .iter()
+            .filter(|(term, _)| haystack.contains(&term.to_lowercase()))
+            .map(|(t, tr)| (t.as_str(), tr.as_str()))
+            .collect()
+    }
+
+    /// Only the entries relevant to this batch, so the prompt stays small.
+    pub fn render_for(&self, text: &str) -> String {
+        self.matching(text)
+            .iter()
+            .map(|(term, translation)| format!("- {} => {}", term, translation))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Speaker name of a `Name: text` style line (the `ID:::` prefix is ignored).
+pub fn speaker_of(line: &str) -> Option<String> {
+    static RE_SPEAKER: OnceLock<regex::Regex> = OnceLock::new();
+    let re = RE_SPEAKER.get_or_init(|| regex::Regex::new(r"^\s*([\p{Lu}][\p{L}'.\- ]{0,30}?)\s*[:：]\s+\S").unwrap());
+    let content = line.split_once(":::").map(|(_, c)| c).unwrap_or(line);
+    re.captures(content).map(|caps| caps[1].trim().to_string())
+}
+
+/// Speaker names of `Name: text` style lines, in order of first appearance.
+pub fn detect_speakers<'a>(lines: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    let mut speakers = Vec::new();
+    for name in lines.into_iter().filter_map(speaker_of) {
+        if seen.insert(name.clone()) {
+            speakers.push(name);
+        }
+    }
+    speakers
+}
+
+/// Templates and data shared by every batch of a job.
+#[derive(Debug, Clone, Default)]
+pub struct PromptTemplates {
+    pub system: String,
+    pub user: String,
+    pub source_lang: String,
+    pub target_lang: String,
+    pub glossary: Glossary,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&'static str, &str)]) -> HashMap<&'static str, String> {
+        pairs.iter().map(|(k, v)| (*k, v.to_string())).collect()
+    }
+
+    #[test]
+    fn renders_known_vars_and_keeps_unknown_ones() {
+        let out = render("{{source_lang}} -> {{ target_lang }}: {{batch}} {{typo}}", &vars(&[("source_lang", "English"), ("target_lang", "Vietnamese"), ("batch", "1:::Hi")]));
+        assert_eq!(out, "English -> Vietnamese: 1:::Hi {{typo}}");
+    }
+
+    #[test]
+    fn values_are_not_rendered_again() {
+        let out = render("{{batch}}", &vars(&[("batch", "1:::{{glossary}}"), ("glossary", "x")]));
+        assert_eq!(out, "1:::{{glossary}}");
+    }
+
+    #[test]
+    fn finds_used_vars() {
+        assert!(uses_var("Lines:\n{{ batch }}\n", "batch"));
+        assert!(!uses_var("Lines:\n{{batches}} {batch}", "batch"));
+    }
+
+    #[test]
+    fn default_templates_contain_the_batch() {
+        assert!(uses_var(DEFAULT_USER_TEMPLATE, "batch"));
+        assert!(uses_var(REVIEW_USER_TEMPLATE, "batch"));
+    }
+
+    #[test]
+    fn splits_front_matter() {
+        let (meta, body) = split_front_matter("---\r\nname: \"Wuthering Waves\"\r\nTemperature: 0.2\r\n---\r\n\r\n# ROLE\n");
+        assert_eq!(meta["name"], "Wuthering Waves");
+        assert_eq!(meta["temperature"], "0.2");
+        assert_eq!(body, "# ROLE\n");
+    }
+
+    #[test]
+    fn unclosed_front_matter_is_body() {
+        let content = "---\nname: x\nno closing fence";
+        let (meta, body) = split_front_matter(content);
+        assert!(meta.is_empty());
+        assert_eq!(body, content);
+    }
+
+    #[test]
+    fn template_names_stay_in_the_prompts_dir() {
+        let dirs = [PathBuf::from("promt")];
+        for name in ["../config.json", "..", "sub/x.md", "sub\\x.md", "/etc/passwd", ""] {
+            let err = read_template(&dirs, name).unwrap_err();
+            assert!(err.contains("must be a file in the prompts directory"), "{}: {}", name, err);
+        }
+    }
+
+    #[test]
+    fn earlier_prompt_dirs_override_later_ones() {
+        let root = std::env::temp_dir().join(format!("prompt-test-{}-overlay", std::process::id()));
+        let (user, bundled) = (root.join("user"), root.join("bundled"));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&user).unwrap();
+        std::fs::create_dir_all(&bundled).unwrap();
+        std::fs::write(bundled.join("Game.md"), "---\nname: Game\n---\nbundled").unwrap();
+        std::fs::write(bundled.join("Other.md"), "other").unwrap();
+        std::fs::write(user.join("Game.md"), "---\nname: Game\n---\nmine").unwrap();
+        let dirs = [user, bundled];
+
+        assert_eq!(list_templates(&dirs), ["Game.md", "Other.md"]);
+        assert_eq!(read_template(&dirs, "Game").unwrap(), "mine");
+        assert_eq!(read_template(&dirs, "Other.md").unwrap(), "other");
+        assert_eq!(find_preset(&dirs, "Game").unwrap().1, "mine");
+        assert_eq!(list_presets(&dirs).len(), 2);
+        assert!(read_template(&dirs, "Missing").is_err());
+    }
+
+    #[test]
+    fn context_block_only_when_there_is_context() {
+        assert_eq!(context_block("", ""), "");
+        let block = context_block("a", "");
+        assert!(block.contains("Before:\na\n"));
+        assert!(!block.contains("After:"));
+        assert!(block.ends_with("Lines to translate:\n"));
+    }
+
+    #[test]
+    fn glossary_matches_case_insensitively() {
+        let glossary = Glossary::parse("# terms\nRover:::Người Lữ Hành\nEcho\tÂm Hưởng\n\n:::nothing\n");
+        assert_eq!(glossary.matching("the rover found an echo"), vec![("Rover", "Người Lữ Hành"), ("Echo", "Âm Hưởng")]);
+        assert_eq!(glossary.render_for("Rover"), "- Rover => Người Lữ Hành");
+        assert!(glossary.matching("nothing here").is_empty());
+    }
+
+    #[test]
+    fn detects_speakers_in_order() {
+        let speakers = detect_speakers(["1:::Jinhsi: Hello there", "2:::Rover: Hi", "3:::Jinhsi: Again", "4:::No speaker here"]);
+        assert_eq!(speakers, vec!["Jinhsi", "Rover"]);
+    }
+}
@@ -0,0 +1,257 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// How the model is asked to return translations.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseMode {
+    /// Plain `ID:::Text` lines.
+    #[default]
+    Text,
+    /// `response_format: json_schema` with a strict schema.
+    JsonSchema,
+    /// `response_format: json_object`; the shape is described in the prompt.
+    JsonObject,
+    /// Forced call of a `submit_translations` function.
+    Tool,
+}
+
+impl ResponseMode {
+    pub fn is_json(&self) -> bool {
+        *self != ResponseMode::Text
+    }
+}
+
+const TOOL_NAME: &str = "submit_translations";
+
+fn translations_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "translations": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "id": {"type": "string"},
+                        "text": {"type": "string"}
+                    },
+                    "required": ["id", "text"],
+                    "additionalProperties": false
+                }
+            }
+        },
+        "required": ["translations"],
+        "additionalProperties": false
+    })
+}
+
+/// Adds `response_format` or `tools` to the request body for the JSON modes.
+pub fn apply_to_payload(mode: ResponseMode, payload: &mut serde_json::Value) {
+    match mode {
+        ResponseMode::Text => {}
+        ResponseMode::JsonSchema => {
+            payload["response_format"] = serde_json::json!({
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "translations",
+                    "strict": true,
+                    "schema": translations_schema()
+                }
+            });
+        }
+        ResponseMode::JsonObject => {
+            payload["response_format"] = serde_json::json!({"type": "json_object"});
+        }
+        ResponseMode::Tool => {
+            payload["tools"] = serde_json::json!([{
+                "type": "function",
+                "function": {
+                    "name": TOOL_NAME,
+                    "description": "Submit one translation per input ID.",
+                    "parameters": translations_schema()
+                }
+            }]);
+            payload["tool_choice"] = serde_json::json!({
+                "type": "function",
+                "function": {"name": TOOL_NAME}
+            });
+        }
+    }
+}
+
+/// Format reminder appended to the user message.
+pub fn format_reminder(mode: ResponseMode) -> &'static str {
+    match mode {
+        ResponseMode::Text => "REMINDER: Format 'ID:::TranslatedText'.",
+        _ => "REMINDER: Reply with JSON only: {\"translations\": [{\"id\": \"<ID>\", \"text\": \"<TranslatedText>\"}]}, exactly one entry per ID, no extra IDs.",
+    }
+}
+
+/// Reads the generated text out of a non-streaming response message.
+pub fn message_text(mode: ResponseMode, message: &serde_json::Value) -> Option<String> {
+    if mode == ResponseMode::Tool {
+        if let Some(args) = message["tool_calls"][0]["function"]["arguments"].as_str() {
+            return Some(args.to_string());
+        }
+    }
+    message["content"].as_str().map(|s| s.to_string())
+}
+
+/// Parses `ID:::Text` output. Lines that do not start with an ID are treated as a
+/// spill of the previous line and joined with a literal `\n`.
+pub fn parse_text(content: &str) -> HashMap<String, String> {
+    static RE_START: OnceLock<regex::Regex> = OnceLock::new();
+    let re_start = RE_START.get_or_init(|| regex::Regex::new(r"^(\d+):::(.*)").unwrap());
+
+    let mut translated_map = HashMap::new();
+    let mut current_id: Option<String> = None;
+    let mut current_text_buffer = String::new();
+
+    for line in content.split('\n') {
+        let trimmed = line.trim();
+        if trimmed.is_empty() { continue; }
+
+        if let Some(caps) = re_start.captures(line) {
+            // FOUND NEW ID
+            // Save previous if exists
+            if let Some(cid) = current_id {
+                translated_map.insert(cid, current_text_buffer.trim().to_string());
+            }
+
+            // Start new
+            current_id = Some(caps[1].to_string());
+            current_text_buffer = caps[2].to_string();
+        } else if current_id.is_some() {
+            // CONTINUATION LINE (AI added a newline)
+            // If we have an active ID, append this line to it using literal \n
+            current_text_buffer.push_str("\\n");
+            current_text_buffer.push_str(trimmed);
+        }
+    }
+
+    // Save last buffer
+    if let Some(cid) = current_id {
+        translated_map.insert(cid, current_text_buffer.trim().to_string());
+    }
+    translated_map
+}
+
+/// Parses JSON output strictly: it must be `{"translations": [{id, text}, ...]}` (or the
+/// bare array) and every ID must appear once. IDs the model left out are simply absent
+/// from the map; IDs outside the batch are kept, as in [`parse_text`], so the caller
+/// can record them as alternates.
+pub fn parse_json(content: &str) -> Result<HashMap<String, String>, String> {
+    let body = strip_code_fence(content);
+    let json: serde_json::Value = serde_json::from_str(body).map_err(|e| format!("Invalid JSON output: {}", e))?;
+    let items = json
+        .get("translations")
+        .unwrap_or(&json)
+        .as_array()
+        .ok_or("JSON output has no 'translations' array")?;
+
+    let mut map = HashMap::new();
+    for item in items {
+        let id = match &item["id"] {
+            serde_json::Value::String(s) => s.trim().to_string(),
+            serde_json::Value::Number(n) => n.to_string(),
+            _ => return Err(format!("JSON entry without id: {}", item)),
+        };
+        let text = item["text"].as_str().ok_or_else(|| format!("JSON entry {} without text", id))?;
+        // Keep the one-line-per-ID file format: real newlines become literal \n.
+        let text = text.trim().replace("\r\n", "\\n").replace('\n', "\\n");
+        if map.insert(id.clone(), text).is_some() {
+            return Err(format!("JSON output repeats ID {}", id));
+        }
+    }
+    Ok(map)
+}
+
+fn strip_code_fence(s: &str) -> &str {
+    let s = s.trim();
+    match s.strip_prefix("```") {
+        Some(rest) => {
+            let rest = rest.strip_prefix("json").unwrap_or(rest);
+            rest.strip_suffix("```").unwrap_or(rest).trim()
+        }
+        None => s,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_lines_by_id() {
+        let map = parse_text("1:::Xin chào\n2::: Tạm biệt \n\n3:::");
+        assert_eq!(map.len(), 3);
+        assert_eq!(map["1"], "Xin chào");
+        assert_eq!(map["2"], "Tạm biệt");
+        assert_eq!(map["3"], "");
+    }
+
+    #[test]
+    fn text_spill_lines_join_with_literal_newline() {
+        let map = parse_text("1:::First part\n  second part\n2:::Next");
+        assert_eq!(map["1"], "First part\\nsecond part");
+        assert_eq!(map["2"], "Next");
+    }
+
+    #[test]
+    fn text_preamble_before_first_id_is_ignored() {
+        let map = parse_text("Here are the translations:\n1:::a");
+        assert_eq!(map.len(), 1);
+        assert_eq!(map["1"], "a");
+    }
+
+    #[test]
+    fn text_keeps_ids_outside_the_batch() {
+        // The caller decides which IDs belong to the batch; the rest become alternates.
+        let map = parse_text("1:::a\n42:::from another batch");
+        assert_eq!(map["42"], "from another batch");
+    }
+
+    #[test]
+    fn text_needs_a_numeric_id_at_line_start() {
+        let map = parse_text("1:::a\n  7:::indented\nabc:::x");
+        assert_eq!(map.len(), 1);
+        assert_eq!(map["1"], "a\\n7:::indented\\nabc:::x");
+    }
+
+    #[test]
+    fn json_object_with_translations() {
+        let map = parse_json(r#"{"translations": [{"id": "1", "text": "Xin chào"}, {"id": "2", "text": " Tạm biệt "}]}"#).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["1"], "Xin chào");
+        assert_eq!(map["2"], "Tạm biệt");
+    }
+
+    #[test]
+    fn json_bare_array_fenced_and_numeric_ids() {
+        let map = parse_json("```json\n[{\"id\": 7, \"text\": \"a\"}]\n```").unwrap();
+        assert_eq!(map["7"], "a");
+    }
+
+    #[test]
+    fn json_newlines_become_literal() {
+        let map = parse_json(r#"{"translations": [{"id": "1", "text": "a\r\nb\nc"}]}"#).unwrap();
+        assert_eq!(map["1"], "a\\nb\\nc");
+    }
+
+    #[test]
+    fn json_keeps_ids_outside_the_batch() {
+        let map = parse_json(r#"{"translations": [{"id": "1", "text": "a"}, {"id": "99", "text": "b"}]}"#).unwrap();
+        assert_eq!(map["99"], "b");
+    }
+
+    #[test]
+    fn json_rejects_malformed_output() {
+        assert!(parse_json(r#"{"translations": [{"id": "1", "text": "a"}"#).is_err());
+        assert!(parse_json(r#"{"result": "a"}"#).is_err());
+        assert!(parse_json(r#"[{"text": "a"}]"#).is_err());
+        assert!(parse_json(r#"[{"id": "1"}]"#).is_err());
+        assert!(parse_json(r#"[{"id": "1", "text": "a"}, {"id": "1", "text": "b"}]"#).is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::prompt::Glossary;
+
+/// Added to the format reminder when the model rates its own lines.
+pub const CONFIDENCE_REMINDER: &str = "After each translated line, append [[confidence:N]] where N is 1 (unsure) to 5 (certain).";
+
+/// Automatic checks of one translated line. Only lines with issues are kept in the
+/// job record; a line without any scores 100.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct LineQuality {
+    pub score: u32,
+    pub issues: Vec<String>,
+    /// Self-rated by the model, 1-5.
+    #[serde(default)]
+    pub confidence: Option<u8>,
+    #[serde(default)]
+    pub glossary_hits: usize,
+    pub source: String,
+    pub translation: String,
+}
+
+/// Removes the `[[confidence:N]]` suffix from each translation and returns the ratings by ID.
+pub fn take_confidence(translations: &mut HashMap<String, String>) -> HashMap<String, u8> {
+    static RE_CONFIDENCE: OnceLock<regex::Regex> = OnceLock::new();
+    let re = RE_CONFIDENCE.get_or_init(|| regex::Regex::new(r"\s*\[\[\s*confidence\s*:\s*([1-5])\s*\]\]\s*$").unwrap());
+    let mut ratings = HashMap::new();
+    for (id, text) in translations.iter_mut() {
+        if let Some(caps) = re.captures(text) {
+            ratings.insert(id.clone(), caps[1].parse().unwrap_or(3));
+            let end = caps.get(0).unwrap().start();
+            text.truncate(end);
+        }
+    }
+    ratings
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Script {
+    Latin,
+    Cjk,
+    Cyrillic,
+    Thai,
+}
+
+fn script_of(c: char) -> Option<Script> {
+    match c {
+        'a'..='z' | 'A'..='Z' | '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}' => Some(Script::Latin),
+        '\u{3040}'..='\u{30FF}' | '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{AC00}'..='\u{D7AF}' => Some(Script::Cjk),
+        '\u{0400}'..='\u{04FF}' => Some(Script::Cyrillic),
+        '\u{0E00}'..='\u{0E7F}' => Some(Script::Thai),
+        _ => None,
+    }
+}
+
+/// Script a language is written in, by the name typed in the settings.
+fn script_of_lang(name: &str) -> Script {
+    match name.trim().to_lowercase().as_str() {
+        "japanese" | "chinese" | "simplified chinese" | "traditional chinese" | "korean" => Script::Cjk,
+        "russian" | "ukrainian" => Script::Cyrillic,
+        "thai" => Script::Thai,
+        _ => Script::Latin,
+    }
+}
+
+/// Script most letters of `text` are written in.
+fn dominant_script(text: &str) -> Option<Script> {
+    let mut counts: Vec<(Script, usize)> = Vec::new();
+    for script in text.chars().filter_map(script_of) {
+        match counts.iter_mut().find(|(s, _)| *s == script) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((script, 1)),
+        }
+    }
+    counts.into_iter().max_by_key(|(_, n)| *n).map(|(s, _)| s)
+}
+
+/// `{0}`, `{name}`, `%s`, `%1$d`, `<tags>`, `[tags]` and literal `\n`, sorted.
+fn placeholders(text: &str) -> Vec<String> {
+    static RE_PLACEHOLDER: OnceLock<regex::Regex> = OnceLock::new();
+    let re = RE_PLACEHOLDER.get_or_init(|| {
+        regex::Regex::new(r"\{[A-Za-z0-9_]*\}|%(\d+\$)?[sdif]|</?[A-Za-z][^>]*>|\[/?[A-Za-z_][^\]]*\]|\\n").unwrap()
+    });
+    let mut found: Vec<String> = re.find_iter(text).map(|m| m.as_str().to_string()).collect();
+    found.sort();
+    found
+}
+
+/// Display width: CJK characters count double, so ratios work across scripts.
+fn width(text: &str) -> usize {
+    text.chars().map(|c| if script_of(c) == Some(Script::Cjk) { 2 } else { 1 }).sum()
+}
+
+/// Runs the automatic checks on one line (contents without the `ID:::` prefix).
+pub fn check(source: &str, translation: &str, source_lang: &str, target_lang: &str, glossary: &Glossary, confidence: Option<u8>) -> LineQuality {
+    let (source, translation) = (source.trim(), translation.trim());
+    let mut issues = Vec::new();
+    let mut penalty = 0;
+
+    if translation.is_empty() {
+        issues.push("empty translation".to_string());
+        penalty += 100;
+    } else {
+        let (expected, actual) = (placeholders(source), placeholders(translation));
+        if expected != actual {
+            let missing: Vec<&str> = expected.iter().filter(|p| !actual.contains(p)).map(|p| p.as_str()).collect();
+            let extra: Vec<&str> = actual.iter().filter(|p| !expected.contains(p)).map(|p| p.as_str()).collect();
+            let detail = if missing.is_empty() && extra.is_empty() {
+                "count differs".to_string()
+            } else {
+                format!("missing [{}] extra [{}]", missing.join(" "), extra.join(" "))
+            };
+            issues.push(format!("placeholders: {}", detail));
+            penalty += 40;
+        }
+
+        if translation.eq_ignore_ascii_case(source) && source.chars().any(|c| c.is_alphabetic()) && source.chars().count() > 3 {
+            issues.push("identical to source".to_string());
+            penalty += 30;
+        } else {
+            // Letters of the source's script left in a target written in another one.
+            let target_script = script_of_lang(target_lang);
+            let source_script = dominant_script(source).unwrap_or_else(|| script_of_lang(source_lang));
+            let mut script_left = false;
+            if source_script != target_script {
+                let letters: Vec<Script> = translation.chars().filter_map(script_of).collect();
+                let leftover = letters.iter().filter(|s| **s == source_script).count();
+                if !letters.is_empty() && leftover * 10 > letters.len() {
+                    issues.push(format!("{} source-script characters left", leftover));
+                    penalty += 25;
+                    script_left = true;
+                }
+            }
+            // Already reported as source-script characters.
+            if !script_left {
+                if let Some(reason) = crate::leftover::detect(source, translation, source_lang, target_lang) {
+                    issues.push(reason);
+                    penalty += 30;
+                }
+            }
+        }
+
+        let source_width = width(source);
+        if source_width >= 10 {
+            let ratio = width(translation) as f64 / source_width as f64;
+            // CJK is denser than its double width suggests.
+            let low = if script_of_lang(target_lang) == Script::Cjk { 0.2 } else { 0.33 };
+            if !(low..=3.0).contains(&ratio) {
+                issues.push(format!("length ratio {:.2}", ratio));
+                penalty += 15;
+            }
+        }
+    }
+
+    let mut glossary_hits = 0;
+    let mut misses = Vec::new();
+    let lowered = translation.to_lowercase();
+    for (term, expected) in glossary.matching(source) {
+        if lowered.contains(&expected.to_lowercase()) {
+            glossary_hits += 1;
+        } else {
+            misses.push(format!("{} => {}", term, expected));
+        }
+    }
+    if !misses.is_empty() {
+        penalty += (misses.len() * 10).min(30);
+        issues.push(format!("glossary: {}", misses.join("; ")));
+    }
+
+    if let Some(rating) = confidence.filter(|r| *r <= 2) {
+        issues.push(format!("self-rated {}/5", rating));
+        penalty += (3 - rating as usize) * 15;
+    }
+
+    LineQuality {
+        score: 100usize.saturating_sub(penalty) as u32,
+        issues,
+        confidence,
+        glossary_hits,
+        source: source.to_string(),
+        translation: translation.to_string(),
+    }
+}
+
+/// Quotes a CSV field when needed.
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_vi(source: &str, translation: &str) -> LineQuality {
+        check(source, translation, "English", "Vietnamese", &Glossary::default(), None)
+    }
+
+    #[test]
+    fn placeholder_mismatch_is_reported() {
+        let quality = check_vi("Deal {0} damage to <b>{target}</b>", "Gây sát thương lên <b>{target}</b>");
+        assert_eq!(quality.issues, ["placeholders: missing [{0}] extra []"]);
+        assert_eq!(quality.score, 60);
+
+        // Order does not matter, only the set.
+        let quality = check_vi("%s hits %d times", "%d lần trúng đòn của %s");
+        assert!(quality.issues.is_empty(), "{:?}", quality.issues);
+        assert_eq!(quality.score, 100);
+    }
+
+    #[test]
+    fn length_ratio_is_checked_for_longer_lines() {
+        let quality = check_vi("This sentence is long enough to be measured", "Dài");
+        assert!(quality.issues.iter().any(|i| i.starts_with("length ratio")), "{:?}", quality.issues);
+        // Short lines vary too much to judge.
+        assert!(check_vi("Yes", "Vâng, được ạ, tôi đồng ý").issues.is_empty());
+    }
+
+    #[test]
+    fn confidence_is_stripped_and_low_ratings_count() {
+        let mut translations: HashMap<String, String> = [
+            ("1".to_string(), "Xin chào [[confidence:2]]".to_string()),
+            ("2".to_string(), "Tạm biệt [[ confidence : 5 ]]".to_string()),
+            ("3".to_string(), "Không có điểm".to_string()),
+        ]
+        .into();
+        let ratings = take_confidence(&mut translations);
+        assert_eq!(translations["1"], "Xin chào");
+        assert_eq!(translations["2"], "Tạm biệt");
+        assert_eq!(translations["3"], "Không có điểm");
+        assert_eq!(ratings, HashMap::from([("1".to_string(), 2), ("2".to_string(), 5)]));
+
+        let quality = check("Hello", "Xin chào", "English", "Vietnamese", &Glossary::default(), Some(2));
+        assert_eq!(quality.issues, ["self-rated 2/5"]);
+        assert_eq!(quality.score, 85);
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+}
@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::translator::TranslatorConfig;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "state", content = "detail")]
+pub enum QueueStatus {
+    Pending,
+    Running,
+    Done,
+    /// Stopped by the user; runs again (resuming) with the rest of the queue.
+    Stopped,
+    Failed(String),
+}
+
+/// A job waiting in the queue, with everything needed to start it later.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QueuedJob {
+    /// Also the job id of its working directory, so an interrupted run resumes
+    /// from its journal.
+    pub id: String,
+    pub added_at: String,
+    pub config: TranslatorConfig,
+    pub file_paths: Vec<String>,
+    pub output_path: Option<String>,
+    pub status: QueueStatus,
+}
+
+/// Jobs run one after another, persisted as `queue.json` in the data dir.
+#[derive(Debug, Default)]
+pub struct JobQueue {
+    path: PathBuf,
+    pub jobs: Vec<QueuedJob>,
+}
+
+impl JobQueue {
+    /// Loads the queue. A job that was running when the app closed is pending again.
+    /// API keys saved by older versions are dropped on the next save.
+    pub fn load(path: &Path) -> Self {
+        let mut jobs: Vec<QueuedJob> = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        for job in jobs.iter_mut().filter(|j| j.status == QueueStatus::Running) {
+            job.status = QueueStatus::Pending;
+        }
+        for job in jobs.iter_mut() {
+            job.config.api_key.clear();
+        }
+        Self { path: path.to_path_buf(), jobs }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(&self.jobs).map_err(|e| e.to_string())?;
+        crate::storage::write_atomic(&self.path, json.as_bytes())
+    }
+
+    /// Adds a job. Its API key is not kept: queue.json is plain text, so the key is
+    /// taken from the settings when the job runs.
+    pub fn push(&mut self, mut config: TranslatorConfig, file_paths: Vec<String>, output_path: Option<String>) -> String {
+        config.api_key.clear();
+        let id = chrono::Local::now().format("%Y%m%d-%H%M%S-%3f").to_string();
+        self.jobs.push(QueuedJob {
+            id: id.clone(),
+            added_at: chrono::Local::now().to_rfc3339(),
+            config,
+            file_paths,
+            output_path,
+            status: QueueStatus::Pending,
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: &str) -> Result<(), String> {
+        match self.jobs.iter().position(|j| j.id == id) {
+            Some(i) if self.jobs[i].status == QueueStatus::Running => Err("Cannot remove a running job".to_string()),
+            Some(i) => {
+                self.jobs.remove(i);
+                Ok(())
+            }
+            None => Err(format!("Job {} is not in the queue", id)),
+        }
+    }
+
+    /// Next job to run: pending or stopped, in queue order.
+    pub fn next_runnable(&self) -> Option<QueuedJob> {
+        self.jobs
+            .iter()
+            .find(|j| matches!(j.status, QueueStatus::Pending | QueueStatus::Stopped))
+            .cloned()
+    }
+
+    pub fn set_status(&mut self, id: &str, status: QueueStatus) {
+        if let Some(job) = self.jobs.iter_mut().find(|j| j.id == id) {
+            job.status = status;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> TranslatorConfig {
+        serde_json::from_value(serde_json::json!({
+            "base_url": "https://api.example.com/v1",
+            "api_key": "sk-secret",
+            "model": "gpt-x",
+            "system_prompt": "",
+            "stream": false,
+            "threads": 1,
+            "batch_size": 10,
+            "delay": 0.0,
+            "last_file": "",
+        }))
+        .unwrap()
+    }
+
+    fn job(id: &str, status: QueueStatus) -> QueuedJob {
+        QueuedJob {
+            id: id.to_string(),
+            added_at: String::new(),
+            config: config(),
+            file_paths: vec!["goc.txt".to_string()],
+            output_path: None,
+            status,
+        }
+    }
+
+    fn queue(statuses: &[(&str, QueueStatus)]) -> JobQueue {
+        JobQueue { path: PathBuf::new(), jobs: statuses.iter().map(|(id, s)| job(id, s.clone())).collect() }
+    }
+
+    #[test]
+    fn load_resets_running_jobs_and_drops_api_keys() {
+        let path = std::env::temp_dir().join(format!("queue-test-{}-load.json", std::process::id()));
+        let jobs = vec![job("a", QueueStatus::Running), job("b", QueueStatus::Done)];
+        std::fs::write(&path, serde_json::to_string(&jobs).unwrap()).unwrap();
+
+        let loaded = JobQueue::load(&path);
+        let statuses: Vec<QueueStatus> = loaded.jobs.iter().map(|j| j.status.clone()).collect();
+        assert_eq!(statuses, vec![QueueStatus::Pending, QueueStatus::Done]);
+        assert!(loaded.jobs.iter().all(|j| j.config.api_key.is_empty()));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn push_does_not_keep_the_api_key() {
+        let mut queue = JobQueue::default();
+        let id = queue.push(config(), vec!["goc.txt".to_string()], None);
+        assert_eq!(queue.jobs.len(), 1);
+        assert_eq!(queue.jobs[0].id, id);
+        assert_eq!(queue.jobs[0].status, QueueStatus::Pending);
+        assert!(queue.jobs[0].config.api_key.is_empty());
+    }
+
+    #[test]
+    fn running_jobs_cannot_be_removed() {
+        let mut queue = queue(&[("a", QueueStatus::Running), ("b", QueueStatus::Pending)]);
+        assert!(queue.remove("a").is_err());
+        assert!(queue.remove("c").is_err());
+        queue.remove("b").unwrap();
+        let ids: Vec<&str> = queue.jobs.iter().map(|j| j.id.as_str()).collect();
+        assert_eq!(ids, vec!["a"]);
+    }
+
+    #[test]
+    fn next_runnable_takes_pending_or_stopped_jobs_in_order() {
+        let mut queue = queue(&[
+            ("a", QueueStatus::Done),
+            ("b", QueueStatus::Failed("timeout".to_string())),
+            ("c", QueueStatus::Stopped),
+            ("d", QueueStatus::Pending),
+        ]);
+        assert_eq!(queue.next_runnable().unwrap().id, "c");
+        queue.set_status("c", QueueStatus::Done);
+        assert_eq!(queue.next_runnable().unwrap().id, "d");
+        queue.set_status("d", QueueStatus::Running);
+        assert!(queue.next_runnable().is_none());
+    }
+}
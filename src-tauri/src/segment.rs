@@ -0,0 +1,373 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// How lines are grouped into batches.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Segmentation {
+    /// Fixed-size batches, extended by neighbours that share a word bigram.
+    #[default]
+    Overlap,
+    /// Runs of consecutive IDs stay together.
+    IdGap,
+    /// Dialogue scenes (speaker lines, scene markers) stay together; short UI strings
+    /// are batched separately.
+    Scene,
+    /// Lines grouped by a leading `[Tag]`/`<tag>`/`{tag}` or embedded markup.
+    Tags,
+    /// Groups read from a sidecar file of `ID:::group` lines.
+    Sidecar,
+}
+
+pub struct SegmentLimits {
+    pub max_lines: usize,
+    pub max_tokens: usize,
+    /// Reference lines added on each side when a group has to be split.
+    pub max_context: usize,
+}
+
+/// Line ranges of one batch (indices into the file).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub context_before: Range<usize>,
+    pub core: Range<usize>,
+    pub context_after: Range<usize>,
+}
+
+/// Reads a sidecar file mapping IDs to group names (`ID:::group` or tab separated).
+pub fn load_sidecar(path: &str) -> Result<HashMap<String, String>, String> {
+    if path.trim().is_empty() {
+        return Err("Sidecar segmentation needs a metadata file".to_string());
+    }
+    let content = std::fs::read_to_string(path.trim()).map_err(|e| format!("Cannot read segment metadata {}: {}", path, e))?;
+    Ok(content
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|l| l.split_once(":::").or_else(|| l.split_once('\t')))
+        .map(|(id, group)| (id.trim().to_string(), group.trim().to_string()))
+        .collect())
+}
+
+/// Splits `lines[start..]` into batches.
+pub fn plan(
+    lines: &[String],
+    start: usize,
+    strategy: Segmentation,
+    sidecar: &HashMap<String, String>,
+    limits: &SegmentLimits,
+) -> Vec<Span> {
+    if strategy == Segmentation::Overlap {
+        return plan_overlap(lines, start, limits);
+    }
+
+    let mut spans = Vec::new();
+    let mut current: Option<(Range<usize>, usize, String)> = None;
+    for Group { class, starts_new, range } in groups(lines, start, strategy, sidecar) {
+        let tokens: usize = lines[range.clone()].iter().map(|l| estimate_tokens(l)).sum();
+
+        if let Some((cur, cur_tokens, cur_class)) = &current {
+            let fits = cur.len() + range.len() <= limits.max_lines && cur_tokens + tokens <= limits.max_tokens;
+            if *cur_class != class || starts_new || !fits {
+                let (cur, _, _) = current.take().unwrap();
+                spans.push(whole(cur));
+            }
+        }
+
+        if range.len() > limits.max_lines || tokens > limits.max_tokens {
+            // A group too large for one batch: chunk it and show the rest of the
+            // group around each chunk as reference.
+            let mut cursor = range.start;
+            while cursor < range.end {
+                let core_end = take_core(lines, cursor, range.end, limits);
+                spans.push(Span {
+                    context_before: cursor.saturating_sub(limits.max_context).max(range.start)..cursor,
+                    core: cursor..core_end,
+                    context_after: core_end..(core_end + limits.max_context).min(range.end),
+                });
+                cursor = core_end;
+            }
+            continue;
+        }
+
+        current = Some(match current.take() {
+            Some((cur, cur_tokens, cur_class)) => (cur.start..range.end, cur_tokens + tokens, cur_class),
+            None => (range, tokens, class),
+        });
+    }
+    if let Some((cur, _, _)) = current {
+        spans.push(whole(cur));
+    }
+    spans
+}
+
+fn whole(core: Range<usize>) -> Span {
+    Span { context_before: core.start..core.start, context_after: core.end..core.end, core }
+}
+
+// Helper: Estimate tokens (char count / 3.5 roughly for mixed content)
+fn estimate_tokens(s: &str) -> usize {
+    (content_of(s).len() as f64 / 3.5) as usize
+}
+
+fn content_of(line: &str) -> &str {
+    line.split_once(":::").map(|(_, c)| c).unwrap_or(line)
+}
+
+/// End (exclusive) of the batch starting at `cursor`, within the line and token limits.
+fn take_core(lines: &[String], cursor: usize, end: usize, limits: &SegmentLimits) -> usize {
+    let mut core_end = cursor;
+    let mut current_tokens = 0;
+    while core_end < end {
+        let tokens = estimate_tokens(&lines[core_end]);
+        // Allow slight overflow if it's the only line.
+        if current_tokens + tokens > limits.max_tokens && core_end > cursor {
+            break;
+        }
+        if core_end - cursor >= limits.max_lines {
+            break;
+        }
+        current_tokens += tokens;
+        core_end += 1;
+    }
+    // Ensure at least one line makes progress
+    core_end.max(cursor + 1)
+}
+
+/// The original strategy: size-limited core, extended by up to `max_context`
+/// neighbours on each side while consecutive lines share a word bigram.
+fn plan_overlap(lines: &[String], start: usize, limits: &SegmentLimits) -> Vec<Span> {
+    let total_lines = lines.len();
+    let mut spans = Vec::new();
+    let mut cursor = start;
+    while cursor < total_lines {
+        let core_end = take_core(lines, cursor, total_lines, limits);
+
+        let mut actual_start = cursor;
+        while cursor - actual_start < limits.max_context
+            && actual_start > start
+            && has_context_overlap(&lines[actual_start - 1], &lines[actual_start])
+        {
+            actual_start -= 1;
+        }
+
+        let mut actual_end = core_end; // Exclusive
+        while actual_end - core_end < limits.max_context
+            && actual_end < total_lines
+            && has_context_overlap(&lines[actual_end - 1], &lines[actual_end])
+        {
+            actual_end += 1;
+        }
+
+        spans.push(Span { context_before: actual_start..cursor, core: cursor..core_end, context_after: core_end..actual_end });
+        // Advance by the core only, to avoid infinite loops or skipping.
+        cursor = core_end;
+    }
+    spans
+}
+
+// Returns true if s1 and s2 share at least one sequence of 2 words (ignoring punctuation/case)
+fn has_context_overlap(s1: &str, s2: &str) -> bool {
+    fn extract_words(s: &str) -> Vec<String> {
+        static RE_WORD: OnceLock<regex::Regex> = OnceLock::new();
+        let re = RE_WORD.get_or_init(|| regex::Regex::new(r"[\w]+").unwrap());
+        re.find_iter(content_of(s)).map(|m| m.as_str().to_lowercase()).collect()
+    }
+
+    let words1 = extract_words(s1);
+    let words2 = extract_words(s2);
+    if words1.len() < 2 || words2.len() < 2 {
+        return false;
+    }
+    let bigrams1: HashSet<(&str, &str)> = words1.windows(2).map(|w| (w[0].as_str(), w[1].as_str())).collect();
+    words2.windows(2).any(|w| bigrams1.contains(&(w[0].as_str(), w[1].as_str())))
+}
+
+/// Contiguous lines of one class.
+struct Group {
+    class: String,
+    /// Starts at an ID gap or scene marker, so it never joins the group before it.
+    starts_new: bool,
+    range: Range<usize>,
+}
+
+/// Contiguous groups of `lines[start..]` with their class. Batches only combine
+/// neighbouring groups of the same class that are not separated by a break.
+fn groups(lines: &[String], start: usize, strategy: Segmentation, sidecar: &HashMap<String, String>) -> Vec<Group> {
+    let mut result: Vec<Group> = Vec::new();
+    let mut state = ClassifyState::default();
+    for (i, line) in lines.iter().enumerate().skip(start) {
+        let (class, new_group) = match classify(line, strategy, sidecar, &mut state) {
+            Some(c) => c,
+            // Blank or unclassifiable lines join whatever came before.
+            None => (result.last().map(|g| g.class.clone()).unwrap_or_default(), false),
+        };
+        match result.last_mut() {
+            Some(last) if last.class == class && !new_group => last.range.end = i + 1,
+            _ => result.push(Group { class, starts_new: new_group, range: i..i + 1 }),
+        }
+    }
+    result
+}
+
+#[derive(Default)]
+struct ClassifyState {
+    prev_id: Option<u64>,
+    prev_class: Option<String>,
+}
+
+/// Class of a line and whether it starts a new group even if the class is unchanged.
+fn classify(line: &str, strategy: Segmentation, sidecar: &HashMap<String, String>, state: &mut ClassifyState) -> Option<(String, bool)> {
+    static RE_SCENE: OnceLock<regex::Regex> = OnceLock::new();
+    static RE_TAG: OnceLock<regex::Regex> = OnceLock::new();
+    static RE_MARKUP: OnceLock<regex::Regex> = OnceLock::new();
+
+    let id = line.split_once(":::").map(|(id, _)| id.trim());
+    let content = content_of(line).trim();
+
+    match strategy {
+        Segmentation::Overlap => None,
+        Segmentation::IdGap => {
+            let id: u64 = id?.parse().ok()?;
+            let gap = state.prev_id.is_some_and(|prev| id != prev + 1);
+            state.prev_id = Some(id);
+            Some(("ids".to_string(), gap))
+        }
+        Segmentation::Scene => {
+            if content.is_empty() {
+                return None;
+            }
+            let re_scene = RE_SCENE.get_or_init(|| {
+                regex::Regex::new(r"(?i)^(\[(scene|chapter|quest|act)\b[^\]]*\]|#{1,3}\s|={3,}|-{3,}|\*{3,}|(chapter|scene|act)\s+\d+)").unwrap()
+            });
+            if re_scene.is_match(content) {
+                state.prev_class = Some("dialogue".to_string());
+                return Some(("dialogue".to_string(), true));
+            }
+            let class = if crate::prompt::speaker_of(content).is_some() {
+                "dialogue".to_string()
+            } else if content.chars().count() <= 40 && !content.ends_with(['.', '!', '?', '…', '。', '！', '？', '"', '”']) {
+                "ui".to_string()
+            } else {
+                // Narration belongs to the scene around it.
+                state.prev_class.clone().unwrap_or_else(|| "dialogue".to_string())
+            };
+            state.prev_class = Some(class.clone());
+            Some((class, false))
+        }
+        Segmentation::Tags => {
+            if content.is_empty() {
+                return None;
+            }
+            let re_tag = RE_TAG.get_or_init(|| regex::Regex::new(r"^[\[<{]([A-Za-z_][\w.\-]*)").unwrap());
+            let re_markup = RE_MARKUP.get_or_init(|| regex::Regex::new(r"\{\d+\}|%[sd]|<[^>]+>").unwrap());
+            let class = match re_tag.captures(content) {
+                Some(caps) => caps[1].to_lowercase(),
+                None if re_markup.is_match(content) => "markup".to_string(),
+                None => "text".to_string(),
+            };
+            Some((class, false))
+        }
+        Segmentation::Sidecar => sidecar.get(id?).map(|group| (group.clone(), false)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|l| l.to_string()).collect()
+    }
+
+    fn limits(max_lines: usize, max_context: usize) -> SegmentLimits {
+        SegmentLimits { max_lines, max_tokens: 5000, max_context }
+    }
+
+    fn cores(spans: &[Span]) -> Vec<Range<usize>> {
+        spans.iter().map(|s| s.core.clone()).collect()
+    }
+
+    fn plan_of(lines: &[String], strategy: Segmentation, limits: &SegmentLimits) -> Vec<Span> {
+        plan(lines, 0, strategy, &HashMap::new(), limits)
+    }
+
+    #[test]
+    fn id_gaps_separate_batches() {
+        let lines = file(&["1:::a", "2:::b", "3:::c", "10:::d", "11:::e", "20:::f"]);
+        assert_eq!(cores(&plan_of(&lines, Segmentation::IdGap, &limits(10, 2))), vec![0..3, 3..5, 5..6]);
+    }
+
+    #[test]
+    fn long_id_run_is_chunked_with_the_rest_of_the_run_as_context() {
+        let lines = file(&["1:::a", "2:::b", "3:::c", "4:::d", "5:::e", "6:::f", "7:::g", "50:::h"]);
+        let spans = plan_of(&lines, Segmentation::IdGap, &limits(3, 2));
+        assert_eq!(cores(&spans), vec![0..3, 3..6, 6..7, 7..8]);
+        assert_eq!(spans[0].context_before, 0..0);
+        assert_eq!(spans[0].context_after, 3..5);
+        assert_eq!(spans[1].context_before, 1..3);
+        // Context stays inside the run.
+        assert_eq!(spans[2].context_after, 7..7);
+        assert_eq!(spans[3].context_before, 7..7);
+    }
+
+    #[test]
+    fn scene_markers_separate_batches() {
+        let lines = file(&[
+            "1:::[Scene 1]",
+            "2:::Rover: Where are we going?",
+            "3:::Jinhsi: To the city.",
+            "4:::[Scene 2]",
+            "5:::Rover: It is late.",
+        ]);
+        assert_eq!(cores(&plan_of(&lines, Segmentation::Scene, &limits(10, 2))), vec![0..3, 3..5]);
+    }
+
+    #[test]
+    fn ui_strings_are_batched_apart_from_dialogue() {
+        let lines = file(&["1:::Rover: Let us go now.", "2:::It was a long night.", "3:::Start Game", "4:::Options", "5:::Jinhsi: Wait for me!"]);
+        assert_eq!(cores(&plan_of(&lines, Segmentation::Scene, &limits(10, 2))), vec![0..2, 2..4, 4..5]);
+    }
+
+    #[test]
+    fn tags_group_lines() {
+        let lines = file(&["1:::[sys] Saved", "2:::[sys] Loaded", "3:::<npc>Hello", "4:::Plain {0} text", "5:::Plain text."]);
+        assert_eq!(cores(&plan_of(&lines, Segmentation::Tags, &limits(10, 2))), vec![0..2, 2..3, 3..4, 4..5]);
+    }
+
+    #[test]
+    fn sidecar_groups_lines() {
+        let sidecar: HashMap<String, String> =
+            [("1", "quest"), ("2", "quest"), ("3", "item"), ("4", "item")].iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let lines = file(&["1:::a", "2:::b", "3:::c", "4:::d", "5:::unlisted"]);
+        let spans = plan(&lines, 0, Segmentation::Sidecar, &sidecar, &limits(10, 2));
+        // Unlisted lines join the group before them.
+        assert_eq!(cores(&spans), vec![0..2, 2..5]);
+    }
+
+    #[test]
+    fn overlap_covers_every_line_once_after_the_header() {
+        let lines = file(&["0:::header", "1:::a", "2:::b", "3:::c", "4:::d", "5:::e"]);
+        let spans = plan(&lines, 1, Segmentation::Overlap, &HashMap::new(), &limits(2, 2));
+        assert_eq!(cores(&spans), vec![1..3, 3..5, 5..6]);
+    }
+
+    #[test]
+    fn overlap_extends_context_over_shared_bigrams() {
+        let lines = file(&["1:::the old tower stands", "2:::near the old tower", "3:::unrelated words here", "4:::nothing in common"]);
+        let spans = plan_of(&lines, Segmentation::Overlap, &limits(1, 2));
+        assert_eq!(spans[0].context_after, 1..2);
+        assert_eq!(spans[1].context_before, 0..1);
+        assert_eq!(spans[2].context_before, 2..2);
+    }
+
+    #[test]
+    fn token_limit_splits_long_lines() {
+        let long = format!("1:::{}", "x".repeat(35));
+        let lines = file(&[&long, &long.replace("1:::", "2:::"), &long.replace("1:::", "3:::")]);
+        let limits = SegmentLimits { max_lines: 10, max_tokens: 15, max_context: 0 };
+        assert_eq!(cores(&plan_of(&lines, Segmentation::Overlap, &limits)), vec![0..1, 1..2, 2..3]);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::usage::Usage;
+
+/// One dispatched server-sent event.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SseEvent {
+    /// Value of the last `event:` field, if any.
+    pub event: Option<String>,
+    /// All `data:` fields of the event joined with `\n`.
+    pub data: String,
+}
+
+/// Incremental SSE decoder following the WHATWG event-stream rules: lines may end in
+/// `\n`, `\r\n` or `\r`, an event ends at a blank line, several `data:` lines are
+/// joined, and `:` lines are comments. Bytes can be fed in arbitrary chunks.
+#[derive(Default)]
+pub struct SseDecoder {
+    line: Vec<u8>,
+    skip_lf: bool,
+    event: Option<String>,
+    data: Option<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        for &b in bytes {
+            if self.skip_lf {
+                self.skip_lf = false;
+                if b == b'\n' {
+                    continue;
+                }
+            }
+            match b {
+                b'\n' => self.end_line(&mut events),
+                b'\r' => {
+                    self.skip_lf = true;
+                    self.end_line(&mut events);
+                }
+                _ => self.line.push(b),
+            }
+        }
+        events
+    }
+
+    /// Flushes whatever is left when the connection closes. Providers sometimes
+    /// omit the final blank line, so a pending event is still dispatched.
+    pub fn finish(&mut self) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        if !self.line.is_empty() {
+            self.end_line(&mut events);
+        }
+        self.dispatch(&mut events);
+        events
+    }
+
+    fn end_line(&mut self, events: &mut Vec<SseEvent>) {
+        let line = std::mem::take(&mut self.line);
+        if line.is_empty() {
+            self.dispatch(events);
+            return;
+        }
+        let line = String::from_utf8_lossy(&line);
+        if line.starts_with(':') {
+            return;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((f, v)) => (f, v.strip_prefix(' ').unwrap_or(v)),
+            None => (line.as_ref(), ""),
+        };
+        match field {
+            "data" => match &mut self.data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => self.data = Some(value.to_string()),
+            },
+            "event" => self.event = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    fn dispatch(&mut self, events: &mut Vec<SseEvent>) {
+        let event = self.event.take();
+        if let Some(data) = self.data.take() {
+            events.push(SseEvent { event, data });
+        }
+    }
+}
+
+/// What one chat-completions stream event carried.
+#[derive(Debug, Default, PartialEq)]
+pub struct ChatDelta {
+    pub content: Option<String>,
+    /// Fragment of the arguments of a streamed tool call.
+    pub tool_arguments: Option<String>,
+    pub finish_reason: Option<String>,
+    pub usage: Option<Usage>,
+    /// The `[DONE]` sentinel.
+    pub done: bool,
+}
+
+/// Interprets an OpenAI-style streaming event. Error events and error objects sent in
+/// the middle of a stream are returned as `Err` so they are not mistaken for content.
+pub fn parse_chat_event(event: &SseEvent) -> Result<ChatDelta, String> {
+    let data = event.data.trim();
+    if data == "[DONE]" {
+        return Ok(ChatDelta { done: true, ..Default::default() });
+    }
+
+    let json: Option<serde_json::Value> = serde_json::from_str(data).ok();
+    if event.event.as_deref() == Some("error") {
+        return Err(match &json {
+            Some(j) => error_message(j).unwrap_or_else(|| data.to_string()),
+            None => data.to_string(),
+        });
+    }
+    let json = json.ok_or_else(|| format!("Malformed stream event: {}", truncate(data, 200)))?;
+    if let Some(msg) = error_message(&json) {
+        return Err(msg);
+    }
+
+    let choice = &json["choices"][0];
+    Ok(ChatDelta {
+        content: choice["delta"]["content"].as_str().map(|s| s.to_string()),
+        tool_arguments: choice["delta"]["tool_calls"][0]["function"]["arguments"]
+            .as_str()
+            .map(|s| s.to_string()),
+        finish_reason: choice["finish_reason"].as_str().map(|s| s.to_string()),
+        usage: Usage::from_json(&json["usage"]),
+        done: false,
+    })
+}
+
+/// Extracts the message of an `{"error": ...}` object, if the value is one.
+pub fn error_message(json: &serde_json::Value) -> Option<String> {
+    let err = json.get("error").filter(|e| !e.is_null())?;
+    Some(
+        err.get("message")
+            .and_then(|m| m.as_str())
+            .map(|m| m.to_string())
+            .or_else(|| err.as_str().map(|s| s.to_string()))
+            .unwrap_or_else(|| err.to_string()),
+    )
+}
+
+fn truncate(s: &str, max: usize) -> &str {
+    match s.char_indices().nth(max) {
+        Some((i, _)) => &s[..i],
+        None => s,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(chunks: &[&[u8]]) -> Vec<SseEvent> {
+        let mut decoder = SseDecoder::new();
+        let mut events = Vec::new();
+        for chunk in chunks {
+            events.extend(decoder.feed(chunk));
+        }
+        events.extend(decoder.finish());
+        events
+    }
+
+    fn data(s: &str) -> SseEvent {
+        SseEvent { event: None, data: s.to_string() }
+    }
+
+    #[test]
+    fn splits_on_blank_lines() {
+        let events = decode_all(&[b"data: a\n\ndata: b\n\n"]);
+        assert_eq!(events, vec![data("a"), data("b")]);
+    }
+
+    #[test]
+    fn handles_crlf_and_cr_framing() {
+        let events = decode_all(&[b"data: a\r\n\r\ndata: b\r\rdata: c\n\n"]);
+        assert_eq!(events, vec![data("a"), data("b"), data("c")]);
+    }
+
+    #[test]
+    fn crlf_split_across_chunks() {
+        let events = decode_all(&[b"data: a\r", b"\n\r", b"\ndata: b\n\n"]);
+        assert_eq!(events, vec![data("a"), data("b")]);
+    }
+
+    #[test]
+    fn joins_multi_line_data() {
+        let events = decode_all(&[b"data: {\"a\":\ndata: 1}\n\n"]);
+        assert_eq!(events, vec![data("{\"a\":\n1}")]);
+    }
+
+    #[test]
+    fn event_split_mid_line_and_mid_utf8() {
+        let bytes = "data: xin chào\n\n".as_bytes();
+        let (a, b) = bytes.split_at(13);
+        let events = decode_all(&[a, b]);
+        assert_eq!(events, vec![data("xin chào")]);
+    }
+
+    #[test]
+    fn ignores_comments_and_unknown_fields() {
+        let events = decode_all(&[b": keep-alive\nid: 7\nretry: 100\ndata:x\n\n"]);
+        assert_eq!(events, vec![data("x")]);
+    }
+
+    #[test]
+    fn keeps_event_type() {
+        let events = decode_all(&[b"event: error\ndata: {\"message\":\"boom\"}\n\n"]);
+        assert_eq!(events[0].event.as_deref(), Some("error"));
+    }
+
+    #[test]
+    fn flushes_unterminated_event() {
+        let events = decode_all(&[b"data: [DONE]"]);
+        assert_eq!(events, vec![data("[DONE]")]);
+    }
+
+    #[test]
+    fn parses_content_finish_reason_and_usage() {
+        let ev = data(r#"{"choices":[{"delta":{"content":"1:::Xin"},"finish_reason":null}]}"#);
+        let delta = parse_chat_event(&ev).unwrap();
+        assert_eq!(delta.content.as_deref(), Some("1:::Xin"));
+        assert_eq!(delta.finish_reason, None);
+
+        let ev = data(r#"{"choices":[{"delta":{},"finish_reason":"length"}],"usage":{"prompt_tokens":10,"completion_tokens":5}}"#);
+        let delta = parse_chat_event(&ev).unwrap();
+        assert_eq!(delta.finish_reason.as_deref(), Some("length"));
+        assert_eq!(delta.usage, Some(Usage { prompt_tokens: 10, completion_tokens: 5 }));
+
+        assert!(parse_chat_event(&data("[DONE]")).unwrap().done);
+    }
+
+    #[test]
+    fn parses_tool_call_fragments() {
+        let ev = data(r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"transl"}}]}}]}"#);
+        let delta = parse_chat_event(&ev).unwrap();
+        assert_eq!(delta.tool_arguments.as_deref(), Some("{\"transl"));
+        assert_eq!(delta.content, None);
+    }
+
+    #[test]
+    fn surfaces_mid_stream_errors() {
+        let ev = data(r#"{"error":{"message":"Rate limit exceeded","code":429}}"#);
+        assert_eq!(parse_chat_event(&ev).unwrap_err(), "Rate limit exceeded");
+
+        let ev = SseEvent { event: Some("error".into()), data: "upstream closed".into() };
+        assert_eq!(parse_chat_event(&ev).unwrap_err(), "upstream closed");
+
+        assert!(parse_chat_event(&data("{not json")).is_err());
+    }
+}
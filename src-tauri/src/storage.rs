@@ -0,0 +1,179 @@
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+/// How many backups of each file are kept.
+const BACKUPS_KEPT: usize = 10;
+
+/// Writes `contents` to a temporary file next to `path`, syncs it and renames it over
+/// `path`, so a crash leaves either the old or the new file, never half of one.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let file_name = path.file_name().and_then(|n| n.to_str()).ok_or_else(|| format!("Invalid path {}", path.display()))?;
+    let tmp_path = dir.join(format!(".{}.tmp", file_name));
+
+    let result = (|| {
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(&tmp_path, path)?;
+        sync_dir(dir);
+        Ok::<(), std::io::Error>(())
+    })();
+    result.map_err(|e| {
+        let _ = std::fs::remove_file(&tmp_path);
+        format!("Cannot write {}: {}", path.display(), e)
+    })
+}
+
+/// Whether `name` is a single file or directory name, so joining it onto a directory
+/// cannot point outside of it.
+pub fn is_plain_name(name: &str) -> bool {
+    !name.contains(['/', '\\']) && matches!(Path::new(name).components().collect::<Vec<_>>().as_slice(), [Component::Normal(_)])
+}
+
+/// Makes the rename itself durable. Directories cannot be opened for syncing on Windows.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) {
+    if let Ok(d) = std::fs::File::open(dir) {
+        let _ = d.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) {}
+
+/// Copies an existing `path` to `backup_dir/<stem>.<path hash>.<timestamp>.<ext>` and
+/// prunes the oldest backups of that file. Does nothing if `path` does not exist yet.
+pub fn backup(path: &Path, backup_dir: &Path) -> Result<Option<PathBuf>, String> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    std::fs::create_dir_all(backup_dir).map_err(|e| e.to_string())?;
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("backup");
+    let ext = path.extension().and_then(|s| s.to_str()).map(|e| format!(".{}", e)).unwrap_or_default();
+    let prefix = format!("{}.{}.", stem, path_hash(path));
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f");
+    let target = backup_dir.join(format!("{}{}{}", prefix, stamp, ext));
+    std::fs::copy(path, &target).map_err(|e| format!("Cannot back up {}: {}", path.display(), e))?;
+    prune(backup_dir, &prefix, &ext);
+    Ok(Some(target))
+}
+
+/// Short hash of the full path, so files of the same name in different directories
+/// keep their own backups. FNV-1a, which unlike the std hasher is stable across builds.
+fn path_hash(path: &Path) -> String {
+    let full = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let hash = full
+        .to_string_lossy()
+        .bytes()
+        .fold(0x811c_9dc5_u32, |hash, b| (hash ^ u32::from(b)).wrapping_mul(0x0100_0193));
+    format!("{:08x}", hash)
+}
+
+fn prune(backup_dir: &Path, prefix: &str, ext: &str) {
+    let mut backups: Vec<PathBuf> = std::fs::read_dir(backup_dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| {
+                    p.file_name()
+                        .and_then(|n| n.to_str())
+                        .is_some_and(|n| n.starts_with(prefix) && n.ends_with(ext))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    // Timestamps sort lexically.
+    backups.sort();
+    let excess = backups.len().saturating_sub(BACKUPS_KEPT);
+    for old in &backups[..excess] {
+        let _ = std::fs::remove_file(old);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("storage-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn write_atomic_replaces_an_existing_file() {
+        let dir = temp_dir("replace");
+        let path = dir.join("out.txt");
+        std::fs::write(&path, "old content that is longer").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(entries(&dir), ["out.txt"]);
+    }
+
+    #[test]
+    fn failed_write_leaves_no_temp_file() {
+        let dir = temp_dir("failed");
+        // A directory cannot be replaced by a file.
+        let path = dir.join("taken");
+        std::fs::create_dir(&path).unwrap();
+        std::fs::write(path.join("inside.txt"), "x").unwrap();
+        assert!(write_atomic(&path, b"new").unwrap_err().starts_with("Cannot write"));
+        assert_eq!(entries(&dir), ["taken"]);
+    }
+
+    #[test]
+    fn same_named_files_keep_separate_backups() {
+        let dir = temp_dir("same-name");
+        let (a, b, backups) = (dir.join("a/tran.txt"), dir.join("b/tran.txt"), dir.join("backups"));
+        for (path, content) in [(&a, "a"), (&b, "b")] {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        assert_eq!(backup(&dir.join("a/missing.txt"), &backups).unwrap(), None);
+        for _ in 0..BACKUPS_KEPT {
+            backup(&a, &backups).unwrap();
+            // Backups are told apart by their millisecond timestamp.
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        let of_b = backup(&b, &backups).unwrap().unwrap();
+        assert_eq!(std::fs::read_to_string(&of_b).unwrap(), "b");
+
+        // Backing up `b` did not prune any of `a`'s.
+        let names = entries(&backups);
+        assert_eq!(names.len(), BACKUPS_KEPT + 1);
+        let prefix_a = format!("tran.{}.", path_hash(&a));
+        assert_ne!(path_hash(&a), path_hash(&b));
+        assert_eq!(names.iter().filter(|n| n.starts_with(&prefix_a) && n.ends_with(".txt")).count(), BACKUPS_KEPT);
+    }
+
+    #[test]
+    fn pruning_keeps_the_newest_backups() {
+        let dir = temp_dir("prune");
+        let (path, backups) = (dir.join("config.json"), dir.join("backups"));
+        let mut made = Vec::new();
+        for i in 0..BACKUPS_KEPT + 3 {
+            std::fs::write(&path, i.to_string()).unwrap();
+            made.push(backup(&path, &backups).unwrap().unwrap());
+            // Backups are told apart by their millisecond timestamp.
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        let names = entries(&backups);
+        assert_eq!(names.len(), BACKUPS_KEPT);
+        let kept: Vec<String> = made[3..].iter().map(|p| p.file_name().unwrap().to_string_lossy().to_string()).collect();
+        assert_eq!(names, kept);
+        assert_eq!(std::fs::read_to_string(made.last().unwrap()).unwrap(), (BACKUPS_KEPT + 2).to_string());
+    }
+}
@@ -1,10 +1,27 @@
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use tauri::{AppHandle, Emitter};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tauri::{AppHandle, Emitter, Manager};
 use std::time::{Duration, Instant};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use crate::http::{HttpSettings, SharedClient};
+use crate::inputs;
+use crate::job::{JobFile, JobRecord};
+use crate::journal::{self, Journal, JournalEntry};
+use crate::leftover::{self, LeftoverAction};
+use crate::memory::TranslationMemory;
+use crate::patch::{self, PatchReport};
+use crate::concurrency::{AdaptiveController, ConcurrencyLimiter};
+use crate::prompt::{self, Glossary, PromptPreset, PromptTemplates};
+use crate::protocol::{self, ResponseMode};
+use crate::quality::{self, LineQuality};
+use crate::queue::{JobQueue, QueueStatus, QueuedJob};
+use crate::segment::{self, SegmentLimits, Segmentation};
+use crate::storage;
+use crate::sse::{self, SseDecoder};
+use crate::usage::{self, BudgetLimits, ModelPrice, Usage, UsageRecord, UsageSummary, UsageTotals, UsageTracker};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TranslatorConfig {
@@ -21,6 +38,228 @@ pub struct TranslatorConfig {
     pub batch_size: usize,
     pub delay: f64,
     pub last_file: String,
+    /// Ask streaming providers to send a final `usage` chunk (`stream_options.include_usage`).
+    #[serde(default = "default_true")]
+    pub include_usage: bool,
+    /// Price table keyed by model name (`"*"` matches any model), USD per 1M tokens.
+    #[serde(default)]
+    pub prices: HashMap<String, ModelPrice>,
+    /// Spending caps. Once crossed, no new batches are dispatched.
+    #[serde(default)]
+    pub max_job_cost: Option<f64>,
+    #[serde(default)]
+    pub max_job_tokens: Option<u64>,
+    #[serde(default)]
+    pub max_daily_cost: Option<f64>,
+    #[serde(default)]
+    pub max_daily_tokens: Option<u64>,
+    /// Let an AIMD controller tune the worker count, starting from `threads`.
+    #[serde(default)]
+    pub adaptive_threads: bool,
+    /// Upper bound for the adaptive controller (defaults to twice `threads`).
+    #[serde(default)]
+    pub max_threads: Option<usize>,
+    /// Seconds allowed to establish a connection. `null` disables the limit.
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout: Option<f64>,
+    /// Seconds allowed for a whole request, including the streamed body.
+    #[serde(default)]
+    pub request_timeout: Option<f64>,
+    /// Seconds a stream may go without sending any bytes before it counts as stalled.
+    #[serde(default = "default_stream_idle_timeout")]
+    pub stream_idle_timeout: Option<f64>,
+    /// e.g. `http://proxy.corp:8080` or `socks5://127.0.0.1:1080`. Empty = no proxy.
+    #[serde(default)]
+    pub proxy_url: String,
+    /// PEM file with extra root certificates (self-signed local gateways).
+    #[serde(default)]
+    pub ca_bundle_path: String,
+    /// Sent with every request, e.g. `OpenAI-Organization` or OpenRouter's `HTTP-Referer`.
+    #[serde(default)]
+    pub extra_headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub user_agent: String,
+    /// `text` (ID:::Text lines), `json_schema`, `json_object` or `tool`. JSON modes fall
+    /// back to text automatically if the provider rejects them.
+    #[serde(default)]
+    pub response_mode: ResponseMode,
+    /// Language names available to templates as `{{source_lang}}` / `{{target_lang}}`.
+    #[serde(default = "default_source_lang")]
+    pub source_lang: String,
+    #[serde(default = "default_target_lang")]
+    pub target_lang: String,
+    /// Prompt preset (by name) from the prompts directory. Supplies the system prompt and,
+    /// if its front matter says so, the language pair. Takes precedence over `system_template`.
+    #[serde(default)]
+    pub prompt_preset: String,
+    /// Template file in the prompts directory used as system prompt. Empty = `system_prompt`.
+    #[serde(default)]
+    pub system_template: String,
+    /// Template file for the user message. Empty = batch lines + format reminder.
+    #[serde(default)]
+    pub user_template: String,
+    /// `term:::translation` file; matching entries are offered as `{{glossary}}`.
+    #[serde(default)]
+    pub glossary_path: String,
+    /// Number of preceding lines offered as `{{previous_lines}}`.
+    #[serde(default = "default_context_lines")]
+    pub context_lines: usize,
+    /// How lines are grouped into batches.
+    #[serde(default)]
+    pub segmentation: Segmentation,
+    /// `ID:::group` file used by the sidecar segmentation.
+    #[serde(default)]
+    pub segment_metadata_path: String,
+    /// Languages translated in one job, each into its own output file from the same
+    /// batch plan. Empty = just `target_lang`.
+    #[serde(default)]
+    pub targets: Vec<TargetLanguage>,
+    /// Send every translated batch with its source to a reviewer that returns `OK` or
+    /// a correction per ID.
+    #[serde(default)]
+    pub review: bool,
+    /// Model of the review pass. Empty = `model`.
+    #[serde(default)]
+    pub review_model: String,
+    /// Template file used as review system prompt. Empty = the built-in review prompt.
+    #[serde(default)]
+    pub review_template: String,
+    /// Ask the model to rate each line 1-5; low ratings lower the line's quality score.
+    #[serde(default)]
+    pub self_confidence: bool,
+    /// What to do with lines that still look untranslated after their batch.
+    #[serde(default)]
+    pub leftover_action: LeftoverAction,
+}
+
+/// One output language of a job. Empty fields fall back to the job's settings.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TargetLanguage {
+    pub target_lang: String,
+    #[serde(default)]
+    pub prompt_preset: String,
+    #[serde(default)]
+    pub system_template: String,
+    #[serde(default)]
+    pub glossary_path: String,
+}
+
+fn default_source_lang() -> String {
+    "English".to_string()
+}
+
+fn default_target_lang() -> String {
+    "Vietnamese".to_string()
+}
+
+fn default_context_lines() -> usize {
+    5
+}
+
+fn default_connect_timeout() -> Option<f64> {
+    Some(20.0)
+}
+
+fn default_stream_idle_timeout() -> Option<f64> {
+    Some(60.0)
+}
+
+impl TranslatorConfig {
+    /// The languages of a job; a single target that inherits everything when none are listed.
+    fn targets(&self) -> Vec<TargetLanguage> {
+        if self.targets.is_empty() {
+            vec![TargetLanguage::default()]
+        } else {
+            self.targets.clone()
+        }
+    }
+
+    fn prompt_templates(&self, target: &TargetLanguage, dirs: &[PathBuf]) -> Result<PromptTemplates, String> {
+        // A target with its own system prompt does not inherit the job's preset.
+        let (preset_name, system_template) = if target.prompt_preset.trim().is_empty() && target.system_template.trim().is_empty() {
+            (self.prompt_preset.trim(), self.system_template.trim())
+        } else {
+            (target.prompt_preset.trim(), target.system_template.trim())
+        };
+        let mut source_lang = self.source_lang.clone();
+        let mut target_lang = self.target_lang.clone();
+        let system = if !preset_name.is_empty() {
+            let (preset, body) = prompt::find_preset(dirs, preset_name)?;
+            if let Some(lang) = preset.source_lang { source_lang = lang; }
+            if let Some(lang) = preset.target_lang { target_lang = lang; }
+            body
+        } else if !system_template.is_empty() {
+            prompt::read_template(dirs, system_template)?
+        } else {
+            self.system_prompt.clone()
+        };
+        let user = if self.user_template.trim().is_empty() {
+            prompt::DEFAULT_USER_TEMPLATE.to_string()
+        } else {
+            let user = prompt::read_template(dirs, self.user_template.trim())?;
+            // Without it every request would go out with nothing to translate.
+            if !prompt::uses_var(&user, "batch") {
+                return Err(format!("User template {} has no {{{{batch}}}} placeholder", self.user_template.trim()));
+            }
+            user
+        };
+        if !target.target_lang.trim().is_empty() {
+            target_lang = target.target_lang.trim().to_string();
+        }
+        let glossary_path = if target.glossary_path.trim().is_empty() { &self.glossary_path } else { &target.glossary_path };
+        Ok(PromptTemplates {
+            system,
+            user,
+            source_lang,
+            target_lang,
+            glossary: Glossary::load(glossary_path)?,
+        })
+    }
+
+    /// Review pass templates for the language of `translation`; `None` when review is off.
+    fn review_templates(&self, translation: &PromptTemplates, dirs: &[PathBuf]) -> Result<Option<PromptTemplates>, String> {
+        if !self.review {
+            return Ok(None);
+        }
+        let system = if self.review_template.trim().is_empty() {
+            prompt::DEFAULT_REVIEW_SYSTEM.to_string()
+        } else {
+            prompt::read_template(dirs, self.review_template.trim())?
+        };
+        Ok(Some(PromptTemplates { system, user: prompt::REVIEW_USER_TEMPLATE.to_string(), ..translation.clone() }))
+    }
+
+    fn review_model(&self) -> &str {
+        if self.review_model.trim().is_empty() { &self.model } else { self.review_model.trim() }
+    }
+
+    fn http_settings(&self) -> HttpSettings {
+        HttpSettings {
+            connect_timeout: timeout_from_secs(self.connect_timeout),
+            request_timeout: timeout_from_secs(self.request_timeout),
+            proxy_url: self.proxy_url.clone(),
+            ca_bundle_path: self.ca_bundle_path.clone(),
+            extra_headers: self.extra_headers.clone(),
+            user_agent: self.user_agent.clone(),
+        }
+    }
+
+    fn budget(&self) -> BudgetLimits {
+        BudgetLimits {
+            max_job_cost: self.max_job_cost,
+            max_job_tokens: self.max_job_tokens,
+            max_daily_cost: self.max_daily_cost,
+            max_daily_tokens: self.max_daily_tokens,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn timeout_from_secs(secs: Option<f64>) -> Option<Duration> {
+    secs.filter(|s| *s > 0.0).map(Duration::from_secs_f64)
 }
 
 #[derive(Clone, Serialize)]
@@ -30,20 +269,265 @@ struct ProgressEvent {
     total: usize,
     message: String,
     append: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<UsageTotals>,
+    /// Current worker limit, reported on global (thread 0) updates.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    concurrency: Option<usize>,
+}
+
+impl ProgressEvent {
+    fn new(thread_id: usize, current: usize, total: usize, message: String, append: bool) -> Self {
+        Self { thread_id, current, total, message, append, usage: None, concurrency: None }
+    }
+}
+
+#[derive(Debug)]
+enum ApiError {
+    /// Non-success HTTP status and the response body.
+    Status(reqwest::StatusCode, String),
+    /// Connect or read timeout.
+    Timeout(String),
+    /// The stream stopped sending bytes for longer than `stream_idle_timeout`.
+    Stalled(Duration),
+    /// Error event or error object received in the response body.
+    Stream(String),
+    /// The output did not follow the requested format.
+    Format(String),
+    Other(String),
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::Status(s, body) if body.trim().is_empty() => write!(f, "API Status: {}", s),
+            ApiError::Status(s, body) => write!(f, "API Status: {} ({})", s, body.trim().chars().take(200).collect::<String>()),
+            ApiError::Timeout(m) => write!(f, "Timeout: {}", m),
+            ApiError::Stalled(d) => write!(f, "Stream stalled (no data for {:.0}s)", d.as_secs_f64()),
+            ApiError::Stream(m) => write!(f, "Stream error: {}", m),
+            ApiError::Format(m) => write!(f, "Bad output: {}", m),
+            ApiError::Other(m) => f.write_str(m),
+        }
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            ApiError::Timeout(e.to_string())
+        } else {
+            ApiError::Other(e.to_string())
+        }
+    }
+}
+
+impl ApiError {
+    /// Errors that mean the provider is overloaded and we should back off.
+    fn is_congestion(&self) -> bool {
+        match self {
+            ApiError::Status(s, _) => s.as_u16() == 429 || s.as_u16() == 503,
+            ApiError::Timeout(_) | ApiError::Stalled(_) => true,
+            ApiError::Stream(_) | ApiError::Format(_) | ApiError::Other(_) => false,
+        }
+    }
+
+    /// A 400/422 whose body blames the JSON mode parameters, as opposed to e.g. a
+    /// context-length or unknown-model error.
+    fn rejects_json_mode(&self) -> bool {
+        match self {
+            ApiError::Status(s, body) if s.as_u16() == 400 || s.as_u16() == 422 => {
+                let body = body.to_lowercase();
+                ["response_format", "json_schema", "tools", "tool_choice"].iter().any(|p| body.contains(p))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Lines dispatched together. Only `core` is translated and written back; the
+/// context lines around it are shown to the model as reference.
+struct Batch {
+    /// Index into the job's files.
+    file: usize,
+    context_before: Vec<(usize, String)>,
+    core: Vec<(usize, String)>,
+    context_after: Vec<(usize, String)>,
+}
+
+impl Batch {
+    /// Halves of the core. Each half keeps the context on its outer side and sees up to
+    /// `width` neighbouring lines of the other half as context on its inner side.
+    fn split(self, width: usize) -> (Batch, Batch) {
+        let Batch { file, context_before, mut core, context_after } = self;
+        let second = core.split_off(core.len() / 2);
+        let first_after: Vec<(usize, String)> = second.iter().chain(&context_after).take(width).cloned().collect();
+        let mut second_before: Vec<(usize, String)> = context_before.iter().chain(&core).rev().take(width).cloned().collect();
+        second_before.reverse();
+        (
+            Batch { file, context_before, core, context_after: first_after },
+            Batch { file, context_before: second_before, core: second, context_after },
+        )
+    }
+
+    /// IDs of the first and last line of the core, for messages and the usage log.
+    fn id_range(&self) -> (String, String) {
+        let id = |line: Option<&(usize, String)>| {
+            line.and_then(|(_, l)| l.split(":::").next()).unwrap_or("?").trim().to_string()
+        };
+        (id(self.core.first()), id(self.core.last()))
+    }
+}
+
+/// One input file of a job and its lines translated into one language.
+struct SourceFile {
+    path: String,
+    output_path: PathBuf,
+    prompts: Arc<PromptTemplates>,
+    /// Set when the job has a review pass.
+    review_prompts: Option<Arc<PromptTemplates>>,
+    raw_lines: Vec<String>,
+    /// 1 when the file starts with a `0:::` header line that is copied as-is.
+    start_idx: usize,
+    output: Mutex<Vec<String>>,
+    total_lines: usize,
+    done_lines: AtomicUsize,
+}
+
+impl SourceFile {
+    fn load(path: &str, output_path: PathBuf, prompts: Arc<PromptTemplates>) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        let raw_lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
+        let start_idx = if !raw_lines.is_empty() && raw_lines[0].starts_with("0:::") { 1 } else { 0 };
+
+        let mut initial_output = Vec::new();
+        if start_idx == 1 {
+            initial_output.push(raw_lines[0].clone());
+        }
+        for line in raw_lines.iter().skip(start_idx) {
+            if let Some((id, _)) = line.split_once(":::") {
+                initial_output.push(format!("{}:::", id.trim()));
+            } else {
+                initial_output.push(line.clone());
+            }
+        }
+
+        Ok(Self {
+            path: path.to_string(),
+            output_path,
+            prompts,
+            review_prompts: None,
+            total_lines: raw_lines.len() - start_idx,
+            raw_lines,
+            start_idx,
+            output: Mutex::new(initial_output),
+            done_lines: AtomicUsize::new(0),
+        })
+    }
+
+    /// Name of the output file, which tells both the source and the language apart.
+    fn name(&self) -> String {
+        self.output_path.file_name().and_then(|n| n.to_str()).map(|n| n.to_string()).unwrap_or_else(|| self.path.clone())
+    }
+
+    /// Writes translated `(line, ID:::Text)` pairs and journals them.
+    fn store(&self, file_index: usize, lines: &[(usize, String)], journal: &Journal) -> Result<(), String> {
+        {
+            let mut out = self.output.lock().unwrap();
+            for (idx, text) in lines {
+                out[*idx] = text.clone();
+            }
+        }
+        self.done_lines.fetch_add(lines.len(), Ordering::SeqCst);
+        let entries: Vec<JournalEntry> = lines
+            .iter()
+            .filter_map(|(idx, text)| JournalEntry::from_output(file_index, *idx, text))
+            .collect();
+        journal.append(&entries)
+    }
+}
+
+/// Content of an `ID:::Text` line.
+fn content_of(line: &str) -> &str {
+    line.split_once(":::").map(|(_, c)| c).unwrap_or(line)
+}
+
+#[derive(Clone, Serialize)]
+struct FileProgressEvent {
+    file_index: usize,
+    path: String,
+    output_path: String,
+    target_lang: String,
+    done: usize,
+    total: usize,
+}
+
+fn emit_file_progress(app: &AppHandle, index: usize, file: &SourceFile) {
+    let _ = app.emit("file_progress", FileProgressEvent {
+        file_index: index,
+        path: file.path.clone(),
+        output_path: file.output_path.to_string_lossy().to_string(),
+        target_lang: file.prompts.target_lang.clone(),
+        done: file.done_lines.load(Ordering::SeqCst),
+        total: file.total_lines,
+    });
+}
+
+/// One attempt at translating a batch.
+struct BatchRequest<'a> {
+    lines: &'a [String],
+    /// Reference-only neighbours of `lines`.
+    context_before: &'a [String],
+    context_after: &'a [String],
+    mode: ResponseMode,
+    prompts: &'a PromptTemplates,
+    /// Lines just before the batch, translated where available.
+    previous_lines: String,
+    model: &'a str,
+    /// Source of the lines when `lines` are drafts sent for review.
+    source: Option<&'a [String]>,
+}
+
+struct ApiResponse {
+    lines: Vec<String>,
+    usage: Option<Usage>,
+    /// `stop`, `length`, ... as reported by the provider.
+    finish_reason: Option<String>,
+    /// Number of IDs at the end of the batch that are absent from the output.
+    missing_tail: usize,
+    /// Translations the model returned for IDs outside the batch.
+    extra: Vec<(String, String)>,
+    /// Self-rated confidence by ID, when asked for.
+    confidence: HashMap<String, u8>,
+    /// False when nothing needed a request: memory hits only, or only empty lines.
+    sent: bool,
+}
+
+impl ApiResponse {
+    /// The model hit `max_tokens` and the output is cut off.
+    fn is_truncated(&self) -> bool {
+        self.finish_reason.as_deref() == Some("length")
+    }
 }
 
 struct RateLimiter {
     last_request: Mutex<Instant>,
+    delay_secs: Mutex<f64>,
 }
 
 impl RateLimiter {
     fn new() -> Self {
         Self {
             last_request: Mutex::new(Instant::now().checked_sub(Duration::from_secs(3600)).unwrap_or_else(Instant::now)),
+            delay_secs: Mutex::new(0.0),
         }
     }
 
-    async fn wait(&self, delay_secs: f64) {
+    fn set_delay(&self, delay_secs: f64) {
+        *self.delay_secs.lock().unwrap() = delay_secs.max(0.0);
+    }
+
+    async fn wait(&self) {
+        let delay_secs = *self.delay_secs.lock().unwrap();
         if delay_secs <= 0.0 {
             return;
         }
@@ -68,6 +552,13 @@ pub struct TranslatorState {
     pub stop_flag: Arc<Mutex<bool>>, 
     rate_limiter: Arc<RateLimiter>,
     kill_notify: Arc<Mutex<Arc<tokio::sync::Notify>>>,
+    pause_tx: tokio::sync::watch::Sender<bool>,
+    /// Worker pool of the running job, so its size can be changed live.
+    active_limiter: Mutex<Option<Arc<ConcurrencyLimiter>>>,
+    http: SharedClient,
+    /// Loaded from the data dir on first use.
+    job_queue: Mutex<Option<JobQueue>>,
+    queue_running: AtomicBool,
 }
 
 impl TranslatorState {
@@ -76,7 +567,25 @@ impl TranslatorState {
             stop_flag: Arc::new(Mutex::new(false)),
             rate_limiter: Arc::new(RateLimiter::new()),
             kill_notify: Arc::new(Mutex::new(Arc::new(tokio::sync::Notify::new()))),
+            pause_tx: tokio::sync::watch::channel(false).0,
+            active_limiter: Mutex::new(None),
+            http: SharedClient::new(),
+            job_queue: Mutex::new(None),
+            queue_running: AtomicBool::new(false),
+        }
+    }
+
+    /// Runs `f` on the job queue, then persists it and tells the UI.
+    fn with_queue<R>(&self, app: &AppHandle, f: impl FnOnce(&mut JobQueue) -> R) -> Result<R, String> {
+        let mut guard = self.job_queue.lock().map_err(|e| e.to_string())?;
+        if guard.is_none() {
+            *guard = Some(JobQueue::load(&data_dir(app)?.join("queue.json")));
         }
+        let queue = guard.as_mut().unwrap();
+        let result = f(queue);
+        queue.save()?;
+        let _ = app.emit("queue", &queue.jobs);
+        Ok(result)
     }
 }
 
@@ -98,8 +607,126 @@ fn get_path(filename: &str) -> PathBuf {
     path
 }
 
-fn log_thread_activity(thread_id: usize, start_id: &str, end_id: &str) {
-    let path = get_path("thread.txt");
+/// Prompt folders, searched in order: `promt/` in the config dir for the user's own
+/// templates, `promt/` next to the executable (portable copies, and the source tree in
+/// debug builds), then the one bundled as a resource. A file in an earlier folder
+/// overrides one of the same name in a later folder.
+fn prompt_dirs(app: &AppHandle) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Ok(dir) = app.path().app_config_dir() {
+        dirs.push(dir.join("promt"));
+    }
+    dirs.push(get_path("promt"));
+    if let Ok(dir) = app.path().resource_dir() {
+        dirs.push(dir.join("promt"));
+    }
+    dirs
+}
+
+/// Platform config dir (e.g. `%APPDATA%\\com.codespace.gui`), created on first use.
+fn config_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+/// Platform data dir for job directories, backups and the usage log.
+fn data_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+/// Working directory of job `job_id`. Ids come from the UI and queue.json, so only
+/// plain names are accepted; anything else could point outside `jobs/`.
+fn job_dir(app: &AppHandle, job_id: &str) -> Result<PathBuf, String> {
+    if !storage::is_plain_name(job_id) {
+        return Err(format!("Invalid job id {}", job_id));
+    }
+    Ok(data_dir(app)?.join("jobs").join(job_id))
+}
+
+/// The usage log used to live next to the executable; an old one is carried over once.
+fn usage_log_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let path = data_dir(app)?.join("usage_log.jsonl");
+    let legacy = get_path("usage_log.jsonl");
+    if !path.exists() && legacy.is_file() {
+        let _ = std::fs::copy(&legacy, &path);
+    }
+    Ok(path)
+}
+
+/// `goc.txt` -> `goc.vi.txt`, next to the input file.
+fn default_output_path(input: &Path, target_lang: &str) -> PathBuf {
+    let stem = input.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+    let ext = input.extension().and_then(|s| s.to_str()).unwrap_or("txt");
+    input.with_file_name(format!("{}.{}.{}", stem, lang_code(target_lang), ext))
+}
+
+/// One output per input and target language. An explicit `output_path` only makes
+/// sense for a single file and language; otherwise the outputs go next to their
+/// sources. Refuses plans where two outputs collide or an output would overwrite an
+/// input of the same job.
+fn plan_files(paths: &[String], target_langs: &[String], output_path: Option<String>) -> Result<Vec<JobFile>, String> {
+    let single_output = output_path.filter(|p| !p.trim().is_empty() && paths.len() == 1 && target_langs.len() == 1);
+    let mut files: Vec<JobFile> = Vec::new();
+    for p in paths {
+        for (target, target_lang) in target_langs.iter().enumerate() {
+            let output = match &single_output {
+                Some(o) => PathBuf::from(o.trim()),
+                None => default_output_path(Path::new(p), target_lang),
+            };
+            if let Some(input) = paths.iter().find(|input| same_file(Path::new(input), &output)) {
+                return Err(format!("{} is an input of this job and would be overwritten by a translation", input));
+            }
+            let output = output.to_string_lossy().to_string();
+            if files.iter().any(|f| f.output_file == output) {
+                return Err(format!("Two targets would both be written to {}", output));
+            }
+            files.push(JobFile { target, target_lang: target_lang.clone(), ..JobFile::new(p, &output) });
+        }
+    }
+    Ok(files)
+}
+
+/// Whether `a` and `b` name the same file, however they are spelled.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Short code for a language name as typed in the settings ("Vietnamese" -> "vi").
+fn lang_code(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    let code = match name.as_str() {
+        "vietnamese" | "tiếng việt" => "vi",
+        "english" => "en",
+        "japanese" => "ja",
+        "chinese" | "simplified chinese" => "zh",
+        "traditional chinese" => "zh-tw",
+        "korean" => "ko",
+        "french" => "fr",
+        "german" => "de",
+        "spanish" => "es",
+        "portuguese" => "pt",
+        "russian" => "ru",
+        "thai" => "th",
+        "indonesian" => "id",
+        "italian" => "it",
+        // Anything else is used as typed, so two unknown languages never share a file
+        // name: "Bahasa Indonesia" -> "bahasa-indonesia".
+        _ => {
+            let slug = name.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect::<Vec<_>>().join("-");
+            return if slug.is_empty() { "translated".to_string() } else { slug };
+        }
+    };
+    code.to_string()
+}
+
+fn log_thread_activity(work_dir: &Path, thread_id: usize, start_id: &str, end_id: &str) {
+    let path = work_dir.join("thread.txt");
     let msg = format!("Thread {}: {}-{}\n", thread_id, start_id, end_id);
     // Append or create
     if let Ok(mut file) = std::fs::OpenOptions::new().create(true).append(true).open(path) {
@@ -120,27 +747,87 @@ pub async fn stop_translation(state: tauri::State<'_, TranslatorState>, app: App
         notify.notify_waiters();
     }
 
-    let _ = app.emit("progress", ProgressEvent {
-        thread_id: 0,
-        current: 0,
-        total: 0,
-        message: "⛔ KILLED.".to_string(),
-        append: false,
-    });
+    // Wake a paused dispatcher so it can see the stop flag and exit.
+    state.pause_tx.send_replace(false);
+
+    let _ = app.emit("progress", ProgressEvent::new(0, 0, 0, "⛔ KILLED.".to_string(), false));
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn save_config(config: TranslatorConfig) -> Result<(), String> {
-    let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
-    std::fs::write(get_path("config.json"), json).map_err(|e| e.to_string())?;
+pub async fn pause_translation(state: tauri::State<'_, TranslatorState>, app: AppHandle) -> Result<(), String> {
+    state.pause_tx.send_replace(true);
+    let _ = app.emit("progress", ProgressEvent::new(
+        0,
+        0,
+        0,
+        "⏸ PAUSED. Running batches will finish, no new ones will start.".to_string(),
+        true,
+    ));
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn resume_translation(state: tauri::State<'_, TranslatorState>, app: AppHandle) -> Result<(), String> {
+    state.pause_tx.send_replace(false);
+    let _ = app.emit("progress", ProgressEvent::new(0, 0, 0, "▶ RESUMED.".to_string(), true));
     Ok(())
 }
 
+/// Changes the worker count and/or request delay of the running job. Without a running
+/// job there is nothing to adjust: the next job starts from the values in the settings.
 #[tauri::command]
-pub async fn load_config() -> Result<Option<TranslatorConfig>, String> {
-    let path = get_path("config.json");
+pub async fn adjust_translation(
+    state: tauri::State<'_, TranslatorState>,
+    app: AppHandle,
+    threads: Option<usize>,
+    delay: Option<f64>,
+) -> Result<(), String> {
+    let limiter = state
+        .active_limiter
+        .lock()
+        .map_err(|e| e.to_string())?
+        .clone()
+        .ok_or_else(|| "No job is running; the settings apply when the next job starts".to_string())?;
+    let mut changes = Vec::new();
+    if let Some(threads) = threads {
+        limiter.set_limit(threads);
+        changes.push(format!("threads = {}", limiter.limit()));
+    }
+    if let Some(delay) = delay {
+        state.rate_limiter.set_delay(delay);
+        changes.push(format!("delay = {}s", delay.max(0.0)));
+    }
+    if !changes.is_empty() {
+        let _ = app.emit("progress", ProgressEvent::new(0, 0, 0, format!("⚙ Adjusted: {}", changes.join(", ")), true));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn save_config(app: AppHandle, config: TranslatorConfig) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+    let dir = config_dir(&app)?;
+    let path = dir.join("config.json");
+    // The UI saves on every edit, so only the config the session started with is
+    // backed up; otherwise the backups would fill up with keystrokes.
+    static BACKED_UP: AtomicBool = AtomicBool::new(false);
+    if !BACKED_UP.load(Ordering::SeqCst) {
+        storage::backup(&path, &dir.join("backups"))?;
+        BACKED_UP.store(true, Ordering::SeqCst);
+    }
+    storage::write_atomic(&path, json.as_bytes())
+}
+
+#[tauri::command]
+pub async fn load_config(app: AppHandle) -> Result<Option<TranslatorConfig>, String> {
+    let mut path = config_dir(&app)?.join("config.json");
+    if !path.exists() {
+        // Older versions kept config.json next to the executable; the next save
+        // writes it to the config dir.
+        path = get_path("config.json");
+    }
     if !path.exists() {
         return Ok(None);
     }
@@ -150,13 +837,80 @@ pub async fn load_config() -> Result<Option<TranslatorConfig>, String> {
 }
 
 #[tauri::command]
-pub async fn fetch_models(base_url: String, api_key: String) -> Result<Vec<String>, String> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/models", base_url.trim_end_matches('/'));
+pub async fn get_usage_summary(app: AppHandle, job_id: Option<String>) -> Result<UsageSummary, String> {
+    let records = usage::read_records(&usage_log_path(&app)?);
+    let records: Vec<UsageRecord> = match job_id {
+        Some(id) => records.into_iter().filter(|r| r.job_id == id).collect(),
+        None => records,
+    };
+    Ok(usage::summarize(&records))
+}
+
+/// Job records, newest first.
+#[tauri::command]
+pub async fn list_jobs(app: AppHandle) -> Result<Vec<JobRecord>, String> {
+    let jobs_dir = data_dir(&app)?.join("jobs");
+    let mut jobs: Vec<JobRecord> = std::fs::read_dir(&jobs_dir)
+        .map(|entries| entries.filter_map(|e| e.ok()).filter_map(|e| JobRecord::load(&e.path()).ok()).collect())
+        .unwrap_or_default();
+    jobs.sort_by(|a, b| b.job_id.cmp(&a.job_id));
+    Ok(jobs)
+}
+
+/// Writes the lowest-scoring lines of a job to a CSV file for human review and
+/// returns how many were written.
+#[tauri::command]
+pub async fn export_review_queue(app: AppHandle, job_id: String, output_path: String, limit: Option<usize>) -> Result<usize, String> {
+    let record = JobRecord::load(&job_dir(&app, job_id.trim())?)?;
+    let mut rows: Vec<(&JobFile, &String, &LineQuality)> = record
+        .files
+        .iter()
+        .flat_map(|f| f.quality.iter().map(move |(id, q)| (f, id, q)))
+        .collect();
+    rows.sort_by_key(|(_, _, q)| q.score);
+    rows.truncate(limit.unwrap_or(500));
+
+    // The BOM makes Excel read the file as UTF-8.
+    let mut csv = String::from("\u{feff}file,target_lang,id,score,issues,confidence,source,translation\n");
+    for (file, id, q) in &rows {
+        let fields = [
+            file.output_file.clone(),
+            file.target_lang.clone(),
+            id.to_string(),
+            q.score.to_string(),
+            q.issues.join("; "),
+            q.confidence.map(|c| c.to_string()).unwrap_or_default(),
+            q.source.clone(),
+            q.translation.clone(),
+        ];
+        csv.push_str(&fields.iter().map(|f| quality::csv_field(f)).collect::<Vec<_>>().join(","));
+        csv.push('\n');
+    }
+    storage::write_atomic(Path::new(output_path.trim()), csv.as_bytes())?;
+    Ok(rows.len())
+}
+
+#[tauri::command]
+pub async fn list_prompt_templates(app: AppHandle) -> Result<Vec<String>, String> {
+    Ok(prompt::list_templates(&prompt_dirs(&app)))
+}
+
+#[tauri::command]
+pub async fn list_prompt_presets(app: AppHandle) -> Result<Vec<PromptPreset>, String> {
+    Ok(prompt::list_presets(&prompt_dirs(&app)))
+}
+
+#[tauri::command]
+pub async fn fetch_models(
+    state: tauri::State<'_, TranslatorState>,
+    config: TranslatorConfig,
+) -> Result<Vec<String>, String> {
+    let client = state.http.get(config.http_settings())?;
+    let url = format!("{}/models", config.base_url.trim_end_matches('/'));
     
     let resp = client
         .get(&url)
-        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Authorization", format!("Bearer {}", config.api_key))
         .send()
         .await
         .map_err(|e| e.to_string())?;
@@ -188,8 +942,49 @@ pub async fn fetch_models(base_url: String, api_key: String) -> Result<Vec<Strin
     Ok(models)
 }
 
-fn save_temp_file(lines: &[String]) {
-    let _ = std::fs::write(get_path("temp_translating.txt"), lines.join("\n"));
+/// Up to `count` lines before `first`, using the translation once a batch has filled it in.
+fn previous_lines(source: &[String], output: &[String], start_idx: usize, first: usize, count: usize) -> String {
+    (first.saturating_sub(count).max(start_idx)..first)
+        .map(|i| {
+            let translated = output[i].split_once(":::").is_some_and(|(_, t)| !t.trim().is_empty());
+            if translated { output[i].as_str() } else { source[i].as_str() }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Content of the non-empty context lines, without IDs.
+fn context_text(lines: &[String]) -> String {
+    lines
+        .iter()
+        .map(|l| content_of(l).trim())
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Runs the automatic checks on translated lines; lines with issues go to the record.
+fn score_lines(job_file: &mut JobFile, source: &SourceFile, lines: &[(usize, String)], confidence: &HashMap<String, u8>) {
+    for (idx, line) in lines {
+        let source_text = content_of(&source.raw_lines[*idx]);
+        let Some((id, text)) = line.split_once(":::") else { continue };
+        if source_text.trim().is_empty() {
+            continue;
+        }
+        let prompts = &source.prompts;
+        let id = id.trim();
+        let quality = quality::check(source_text, text, &prompts.source_lang, &prompts.target_lang, &prompts.glossary, confidence.get(id).copied());
+        job_file.set_quality(id, quality);
+    }
+}
+
+/// Snapshot of each file's progress as `temp_<index>_<name>` in the job directory. The
+/// index keeps outputs of the same name from different folders apart.
+fn save_temp_files(work_dir: &Path, files: &[SourceFile]) {
+    for (index, file) in files.iter().enumerate() {
+        let lines = file.output.lock().unwrap().join("\n");
+        let _ = storage::write_atomic(&work_dir.join(format!("temp_{}_{}", index, file.name())), lines.as_bytes());
+    }
 }
 
 #[tauri::command]
@@ -197,312 +992,905 @@ pub async fn start_translation(
     app: AppHandle,
     state: tauri::State<'_, TranslatorState>,
     config: TranslatorConfig,
-    file_path: String,
+    file_paths: Vec<String>,
+    output_path: Option<String>,
+    resume_job_id: Option<String>,
 ) -> Result<(), String> {
-    let kill_signal = Arc::new(tokio::sync::Notify::new());
-    {
-        let mut stop = state.stop_flag.lock().map_err(|e| e.to_string())?;
-        *stop = false;
-        
-        let mut notify_guard = state.kill_notify.lock().map_err(|e| e.to_string())?;
-        *notify_guard = kill_signal.clone();
+    if state.queue_running.load(Ordering::SeqCst) {
+        return Err("The queue is running; stop it before starting another job".to_string());
     }
-    
-    let _ = std::fs::write(get_path("thread.txt"), "");
+    let resume_job_id = resume_job_id.map(|id| id.trim().to_string()).filter(|id| !id.is_empty());
+    if let Some(id) = &resume_job_id {
+        if !job_dir(&app, id)?.join("job.json").exists() {
+            return Err(format!("Job {} not found", id));
+        }
+    }
+    match run_job(&app, &state, config, file_paths, output_path, resume_job_id).await? {
+        JobOutcome::Finished => Ok(()),
+        JobOutcome::Budget(reason) => Err(format!("Budget reached: {}", reason)),
+    }
+}
 
-    let content = std::fs::read_to_string(&file_path).map_err(|e| e.to_string())?;
-    let raw_lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
-    
-    let mut initial_output = Vec::new();
-    let start_idx = if !raw_lines.is_empty() && raw_lines[0].starts_with("0:::") { 1 } else { 0 };
+/// Sets up a job for a new version of a source file: lines whose text did not change
+/// keep their old translation, only new and modified ones are sent. Run it by resuming
+/// the returned job id.
+#[tauri::command]
+pub async fn prepare_patch_job(
+    app: AppHandle,
+    config: TranslatorConfig,
+    old_source: String,
+    old_translation: String,
+    new_source: String,
+    output_path: Option<String>,
+) -> Result<PatchReport, String> {
+    if config.targets.len() > 1 {
+        return Err("A patch job translates into a single language".to_string());
+    }
+    let read = |path: &str| {
+        std::fs::read_to_string(path)
+            .map(|c| c.lines().map(|s| s.to_string()).collect::<Vec<_>>())
+            .map_err(|e| format!("Cannot read {}: {}", path, e))
+    };
+    let patch = patch::diff(&read(&old_source)?, &read(&old_translation)?, &read(&new_source)?);
+
+    let prompts = config.prompt_templates(&config.targets()[0], &prompt_dirs(&app))?;
+    let output = match output_path.filter(|p| !p.trim().is_empty()) {
+        Some(o) => PathBuf::from(o.trim()),
+        None => default_output_path(Path::new(&new_source), &prompts.target_lang),
+    };
+    let job_id = chrono::Local::now().format("%Y%m%d-%H%M%S-%3f").to_string();
+    let work_dir = data_dir(&app)?.join("jobs").join(&job_id);
+    std::fs::create_dir_all(&work_dir).map_err(|e| e.to_string())?;
+
+    // Carried-over lines are journaled as already done, so the job skips them.
+    let entries: Vec<JournalEntry> = patch
+        .carried
+        .iter()
+        .map(|(line, id, text)| JournalEntry { file: 0, line: *line, id: id.clone(), text: text.clone() })
+        .collect();
+    Journal::open(&work_dir.join("journal.jsonl"))?.append(&entries)?;
+    let output_file = output.to_string_lossy().to_string();
+    let job_file = JobFile { target_lang: prompts.target_lang.clone(), ..JobFile::new(&new_source, &output_file) };
+    JobRecord::new(&job_id, vec![job_file]).save(&work_dir)?;
+
+    let report = PatchReport { job_id, old_source, old_translation, new_source, output_file, ..patch.report };
+    let json = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+    storage::write_atomic(&work_dir.join("patch_report.json"), json.as_bytes())?;
+    Ok(report)
+}
 
-    if start_idx == 1 {
-        initial_output.push(raw_lines[0].clone());
+#[tauri::command]
+pub async fn enqueue_job(
+    app: AppHandle,
+    state: tauri::State<'_, TranslatorState>,
+    config: TranslatorConfig,
+    file_paths: Vec<String>,
+    output_path: Option<String>,
+) -> Result<Vec<QueuedJob>, String> {
+    state.with_queue(&app, |queue| {
+        queue.push(config, file_paths, output_path);
+        queue.jobs.clone()
+    })
+}
+
+#[tauri::command]
+pub async fn list_queue(app: AppHandle, state: tauri::State<'_, TranslatorState>) -> Result<Vec<QueuedJob>, String> {
+    state.with_queue(&app, |queue| queue.jobs.clone())
+}
+
+#[tauri::command]
+pub async fn remove_queued_job(app: AppHandle, state: tauri::State<'_, TranslatorState>, id: String) -> Result<Vec<QueuedJob>, String> {
+    if !storage::is_plain_name(&id) {
+        return Err(format!("Invalid job id {}", id));
     }
+    state.with_queue(&app, |queue| queue.remove(&id).map(|_| queue.jobs.clone()))?
+}
 
-    for line in raw_lines.iter().skip(start_idx) {
-        if let Some((id, _)) = line.split_once(":::") {
-            initial_output.push(format!("{}:::", id.trim()));
-        } else {
-            initial_output.push(line.clone());
+/// Runs the pending jobs of the queue one after another. A stop or a budget hit ends
+/// the run; the stopped job resumes from its journal the next time the queue is started.
+#[tauri::command]
+pub async fn run_queue(app: AppHandle, state: tauri::State<'_, TranslatorState>) -> Result<(), String> {
+    if state.queue_running.swap(true, Ordering::SeqCst) {
+        return Err("The queue is already running".to_string());
+    }
+    let result = async {
+        while let Some(job) = state.with_queue(&app, |queue| queue.next_runnable())? {
+            state.with_queue(&app, |queue| queue.set_status(&job.id, QueueStatus::Running))?;
+            // The queue does not store API keys; use the one of the current settings.
+            let mut config = job.config;
+            config.api_key = load_config(app.clone()).await?.map(|c| c.api_key).unwrap_or_default();
+            let result = run_job(&app, &state, config, job.file_paths, job.output_path, Some(job.id.clone())).await;
+            let mut stopped = *state.stop_flag.lock().map_err(|e| e.to_string())?;
+            let status = match result {
+                Ok(JobOutcome::Finished) if stopped => QueueStatus::Stopped,
+                Ok(JobOutcome::Finished) => QueueStatus::Done,
+                // The budget applies to every job; the rest would stop right away.
+                Ok(JobOutcome::Budget(_)) => {
+                    stopped = true;
+                    QueueStatus::Stopped
+                }
+                Err(e) => QueueStatus::Failed(e),
+            };
+            state.with_queue(&app, |queue| queue.set_status(&job.id, status))?;
+            if stopped {
+                break;
+            }
         }
+        Ok(())
+    }
+    .await;
+    state.queue_running.store(false, Ordering::SeqCst);
+    result
+}
+
+/// How a job that did not fail ended.
+enum JobOutcome {
+    /// Completed, or stopped by the user.
+    Finished,
+    /// Stopped by a budget limit, with the reason.
+    Budget(String),
+}
+
+/// State shared by the dispatcher and the workers of one running job.
+struct JobContext {
+    app: AppHandle,
+    config: TranslatorConfig,
+    client: reqwest::Client,
+    job_id: String,
+    work_dir: PathBuf,
+    files: Vec<SourceFile>,
+    journal: Journal,
+    record: Mutex<JobRecord>,
+    /// Shared by all files and languages of the job.
+    memory: TranslationMemory,
+    usage: UsageTracker,
+    usage_log: PathBuf,
+    provider: String,
+    /// Workers push the halves of truncated batches back onto the front.
+    queue: Mutex<VecDeque<Batch>>,
+    total_batches: AtomicUsize,
+    finished_batches: AtomicUsize,
+    /// Context the halves of a split batch get from each other.
+    max_context: usize,
+    /// Set once the provider turns out not to support the configured JSON mode.
+    text_fallback: AtomicBool,
+    format_failures: AtomicUsize,
+    /// `(file, line)` of the lines already sent back once for looking untranslated.
+    requeued: Mutex<HashSet<(usize, usize)>>,
+    stop_flag: Arc<Mutex<bool>>,
+    kill_signal: Arc<tokio::sync::Notify>,
+    rate_limiter: Arc<RateLimiter>,
+    limiter: Arc<ConcurrencyLimiter>,
+    adaptive: Option<AdaptiveController>,
+}
+
+impl JobContext {
+    fn stopped(&self) -> bool {
+        *self.stop_flag.lock().unwrap()
     }
 
-    let mut work_items = Vec::new();
-    for (i, line) in raw_lines.iter().enumerate().skip(start_idx) {
-        work_items.push((i, line.clone()));
+    fn emit(&self, event: ProgressEvent) {
+        let _ = self.app.emit("progress", event);
     }
 
-    // === DYNAMIC BATCHING LOGIC ===
-    let batch_limit_size = config.batch_size.max(1);
-    let token_limit = 5000;
-    
-    let mut batches: Vec<Vec<(usize, String)>> = Vec::new();
-    let mut cursor = start_idx;
-    let total_lines = raw_lines.len();
+    /// Worker limit to report with an event; only changes when the controller is on.
+    fn adaptive_limit(&self) -> Option<usize> {
+        self.adaptive.as_ref().map(|_| self.limiter.limit())
+    }
 
-    // Helper: Estimate tokens (char count / 3.5 roughly for mixed content)
-    fn estimate_tokens(s: &str) -> usize {
-        let content = s.split_once(":::").map(|(_, c)| c).unwrap_or(s);
-        (content.len() as f64 / 3.5) as usize
+    fn response_mode(&self) -> ResponseMode {
+        if self.text_fallback.load(Ordering::SeqCst) { ResponseMode::Text } else { self.config.response_mode }
     }
 
-    // Helper: Check for bigram overlap (2 consecutive words)
-    // Returns true if s1 and s2 share at least one sequence of 2 words (ignoring punctuation/case)
-    fn has_context_overlap(s1: &str, s2: &str) -> bool {
-        fn extract_words(s: &str) -> Vec<String> {
-            let re = regex::Regex::new(r"[\w]+").unwrap();
-            let content = s.split_once(":::").map(|(_, c)| c).unwrap_or(s);
-            re.find_iter(content)
-              .map(|m| m.as_str().to_lowercase())
-              .collect()
+    /// Adds the usage of one request to the job totals and the usage log. Called for
+    /// every response that reports usage, including ones that then fail.
+    fn record_usage(&self, start_id: &str, end_id: &str, model: &str, u: &Usage) {
+        let cost = usage::cost_of(&self.config.prices, model, u);
+        self.usage.record(u, cost);
+        usage::append_record(&self.usage_log, &UsageRecord {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            job_id: self.job_id.clone(),
+            provider: self.provider.clone(),
+            model: model.to_string(),
+            start_id: start_id.to_string(),
+            end_id: end_id.to_string(),
+            prompt_tokens: u.prompt_tokens,
+            completion_tokens: u.completion_tokens,
+            cost,
+        });
+    }
+
+    /// A 400/422 naming response_format/tools means the provider does not support
+    /// them; several malformed replies in a row mean it ignores them. Either way, the
+    /// whole job switches to the text protocol.
+    fn note_json_result(&self, mode: ResponseMode, result: &Result<ApiResponse, ApiError>) {
+        if !mode.is_json() {
+            return;
         }
+        let unsupported = match result {
+            Err(e) if e.rejects_json_mode() => true,
+            Err(ApiError::Format(_)) => self.format_failures.fetch_add(1, Ordering::SeqCst) + 1 >= 3,
+            Err(_) => false,
+            Ok(_) => {
+                self.format_failures.store(0, Ordering::SeqCst);
+                false
+            }
+        };
+        if unsupported && !self.text_fallback.swap(true, Ordering::SeqCst) {
+            self.emit(ProgressEvent::new(0, 0, 0, "JSON output mode not supported by provider, falling back to ID:::Text.".to_string(), true));
+        }
+    }
 
-        let words1 = extract_words(s1);
-        let words2 = extract_words(s2);
+    /// Feeds the outcome of a request to the concurrency controller, if it is on.
+    /// `started` is taken after the rate-limiter wait, which is not provider latency.
+    fn note_latency(&self, result: &Result<ApiResponse, ApiError>, started: Instant, lines: usize) {
+        let Some(adaptive) = &self.adaptive else { return };
+        match result {
+            // Answers that needed no request say nothing about the provider.
+            Ok(response) if response.sent => {
+                adaptive.on_success(started.elapsed() / lines.max(1) as u32);
+            }
+            Ok(_) => {}
+            Err(e) if e.is_congestion() => {
+                adaptive.on_congestion();
+            }
+            Err(_) => {}
+        }
+    }
 
-        if words1.len() < 2 || words2.len() < 2 { return false; }
+    /// Counts a finished batch and updates the global progress (thread 0).
+    fn batch_finished(&self, usage: Option<UsageTotals>) {
+        let finished = self.finished_batches.fetch_add(1, Ordering::SeqCst) + 1;
+        let total = self.total_batches.load(Ordering::SeqCst);
+        self.emit(ProgressEvent {
+            usage,
+            concurrency: Some(self.limiter.limit()),
+            ..ProgressEvent::new(0, finished, total, format!("Progress: {}/{} Batches", finished, total), false)
+        });
+    }
+}
 
-        let bigrams1: std::collections::HashSet<(String, String)> = words1
-            .windows(2)
-            .map(|w| (w[0].clone(), w[1].clone()))
-            .collect();
+/// Runs one job to completion, stop or budget. With `job_id` set, an existing job of
+/// that id is resumed from its journal; otherwise a new job is created under that id.
+async fn run_job(
+    app: &AppHandle,
+    state: &TranslatorState,
+    config: TranslatorConfig,
+    file_paths: Vec<String>,
+    output_path: Option<String>,
+    job_id: Option<String>,
+) -> Result<JobOutcome, String> {
+    let kill_signal = Arc::new(tokio::sync::Notify::new());
+    {
+        let mut stop = state.stop_flag.lock().map_err(|e| e.to_string())?;
+        *stop = false;
 
-        for w in words2.windows(2) {
-             if bigrams1.contains(&(w[0].clone(), w[1].clone())) {
-                 return true;
-             }
+        let mut notify_guard = state.kill_notify.lock().map_err(|e| e.to_string())?;
+        *notify_guard = kill_signal.clone();
+    }
+    state.pause_tx.send_replace(false);
+    let mut pause_rx = state.pause_tx.subscribe();
+
+    // Temp and state files of this job, so concurrent jobs never share them.
+    // Resuming reuses the job's directory, record, file list and journal.
+    // Also covers the ids of queued jobs, which come from queue.json.
+    let resumed = match job_id.as_deref().map(|id| job_dir(app, id)).transpose()? {
+        Some(dir) if dir.join("job.json").exists() => Some(JobRecord::load(&dir)?),
+        _ => None,
+    };
+    let resuming = resumed.is_some();
+    let dirs = prompt_dirs(app);
+    let mut prompts = Vec::new();
+    let mut review_prompts = Vec::new();
+    for target in &config.targets() {
+        let templates = config.prompt_templates(target, &dirs)?;
+        review_prompts.push(config.review_templates(&templates, &dirs)?.map(Arc::new));
+        prompts.push(Arc::new(templates));
+    }
+    let mut record = match resumed {
+        Some(record) => record,
+        None => {
+            let target_langs: Vec<String> = prompts.iter().map(|p| p.target_lang.clone()).collect();
+            let lang_codes: Vec<String> = target_langs.iter().map(|l| lang_code(l)).collect();
+            let paths = inputs::expand(&file_paths, &lang_codes)?;
+            let files = plan_files(&paths, &target_langs, output_path)?;
+            let id = job_id.unwrap_or_else(|| chrono::Local::now().format("%Y%m%d-%H%M%S-%3f").to_string());
+            JobRecord::new(&id, files)
         }
-        false
+    };
+    let job_id = record.job_id.clone();
+    let work_dir = job_dir(app, &job_id)?;
+    std::fs::create_dir_all(&work_dir).map_err(|e| e.to_string())?;
+    let journal_path = work_dir.join("journal.jsonl");
+    let usage_log = usage_log_path(app)?;
+
+    let mut files = Vec::new();
+    for f in &record.files {
+        let target_prompts = prompts
+            .get(f.target)
+            .ok_or_else(|| format!("Job {} has more target languages than the current settings", job_id))?;
+        let source = SourceFile::load(&f.source_file, PathBuf::from(&f.output_file), target_prompts.clone())?;
+        files.push(SourceFile { review_prompts: review_prompts[f.target].clone(), ..source });
     }
+    let memory = TranslationMemory::new();
+    let done = if resuming { replay_journal(&files, &memory, &journal_path) } else { HashSet::new() };
+    let limits = SegmentLimits { max_lines: config.batch_size.max(1), max_tokens: 5000, max_context: 5 };
+    let batches = plan_batches(&files, &config, &limits, &done)?;
 
-    while cursor < total_lines {
-        // 1. Build "Core" Batch
-        let mut core_end = cursor; // Exclusive
-        let mut current_tokens = 0;
-        let mut current_lines = 0;
+    let rate_limiter = state.rate_limiter.clone();
+    rate_limiter.set_delay(config.delay);
+
+    // A resumed job counts what its earlier runs spent against the job limits.
+    let usage_records = usage::read_records(&usage_log);
+    let usage_tracker = UsageTracker::resume(usage::job_totals(&usage_records, &job_id));
+    let budget = config.budget();
+    let spent_today = usage::day_totals(&usage_records, &chrono::Local::now().format("%Y-%m-%d").to_string());
+    let mut budget_reason: Option<String> = None;
+
+    record.finished = false;
+    // Written up front so the job can be resumed even if the app dies mid-run.
+    record.save(&work_dir)?;
+
+    let client = state.http.get(config.http_settings())?;
+    let limiter = Arc::new(ConcurrencyLimiter::new(config.threads));
+    *state.active_limiter.lock().map_err(|e| e.to_string())? = Some(limiter.clone());
+    let adaptive = config.adaptive_threads.then(|| {
+        let max = config.max_threads.unwrap_or(config.threads.max(1) * 2);
+        AdaptiveController::new(limiter.clone(), 1, max)
+    });
 
-        while core_end < total_lines {
-            let tokens = estimate_tokens(&raw_lines[core_end]);
-            
-            // Check limits. Allow slight overflow if it's the only line, 
-            // but generally stop if adding this line exceeds limit significantly.
-            // User requested < 200 diff, so we stop if we are already near 6000.
-            if current_tokens + tokens > token_limit && current_lines > 0 {
-                break;
-            }
-            if current_lines >= batch_limit_size {
+    let ctx = Arc::new(JobContext {
+        app: app.clone(),
+        client,
+        job_id,
+        work_dir,
+        journal: Journal::open(&journal_path)?,
+        record: Mutex::new(record),
+        memory,
+        usage: usage_tracker,
+        usage_log,
+        provider: usage::provider_of(&config.base_url),
+        total_batches: AtomicUsize::new(batches.len()),
+        queue: Mutex::new(VecDeque::from(batches)),
+        finished_batches: AtomicUsize::new(0),
+        max_context: limits.max_context,
+        text_fallback: AtomicBool::new(false),
+        format_failures: AtomicUsize::new(0),
+        requeued: Mutex::new(HashSet::new()),
+        stop_flag: state.stop_flag.clone(),
+        kill_signal,
+        rate_limiter,
+        limiter,
+        adaptive,
+        files,
+        config,
+    });
+    let mut tasks = tokio::task::JoinSet::new();
+
+    // Initial Status
+    for (index, file) in ctx.files.iter().enumerate() {
+        emit_file_progress(app, index, file);
+    }
+    let total = ctx.total_batches.load(Ordering::SeqCst);
+    ctx.emit(ProgressEvent {
+        concurrency: Some(ctx.limiter.limit()),
+        ..ProgressEvent::new(0, 0, total, format!("Started. {} Batches.", total), false)
+    });
+
+    // Dispatch batches
+    let mut dispatched = 0;
+    loop {
+        let next = ctx.queue.lock().unwrap().pop_front();
+        let Some(batch) = next else {
+            // Queue drained, but a running batch may still split and refill it.
+            if tasks.join_next().await.is_none() {
                 break;
             }
+            continue;
+        };
+        dispatched += 1;
+        let thread_id = dispatched; // Thread 1, 2, 3...
 
-            current_tokens += tokens;
-            current_lines += 1;
-            core_end += 1;
-        }
-        
-        // Ensure at least one line makes progress
-        if core_end == cursor { 
-            core_end += 1; 
-        }
-
-        // 2. Expand Backward (Context Lookback)
-        let mut actual_start = cursor;
-        let mut lookback_count = 0;
-        while lookback_count < 5 && actual_start > start_idx {
-            let prev = actual_start - 1;
-            let curr = actual_start;
-            if has_context_overlap(&raw_lines[prev], &raw_lines[curr]) {
-                actual_start = prev;
-                lookback_count += 1;
-            } else {
-                break;
+        // Wait for worker slot. This blocks until a thread is free.
+        // While paused we hold off here; batches already running are left alone.
+        let permit = loop {
+            let _ = pause_rx.wait_for(|paused| !*paused).await;
+            let permit = ctx.limiter.acquire().await?;
+            if !*pause_rx.borrow() {
+                break permit;
             }
+        };
+
+        if ctx.stopped() {
+            break;
         }
 
-        // 3. Expand Forward (Context Lookahead)
-        let mut actual_end = core_end; // Exclusive
-        let mut lookahead_count = 0;
-        while lookahead_count < 5 && actual_end < total_lines {
-            let prev = actual_end - 1; // Last line of current selection
-            let curr = actual_end;     // Candidate to add
-            if has_context_overlap(&raw_lines[prev], &raw_lines[curr]) {
-                actual_end = curr + 1; // Include this line
-                lookahead_count += 1;
-            } else {
+        // Batches finishing while we waited may have pushed us over the budget.
+        // Stop handing out work; in-flight batches are allowed to complete.
+        if !budget.is_unlimited() {
+            if let Some(reason) = budget.exceeded(&ctx.usage.job_totals(), &ctx.usage.run_totals(), &spent_today) {
+                budget_reason = Some(reason);
                 break;
             }
         }
 
-        // 4. Create Batch Output
-        let mut batch_items = Vec::new();
-        // map input index back to original raw_lines index
-        for i in actual_start..actual_end {
-            batch_items.push((i, raw_lines[i].clone()));
+        let ctx = ctx.clone();
+        tasks.spawn(async move {
+            let _permit = permit; // drop when finished
+            run_batch(&ctx, batch, thread_id).await;
+        });
+    }
+
+    while tasks.join_next().await.is_some() {}
+    *state.active_limiter.lock().map_err(|e| e.to_string())? = None;
+    report_record(&ctx);
+
+    if let Some(reason) = budget_reason {
+        save_temp_files(&ctx.work_dir, &ctx.files);
+        ctx.emit(ProgressEvent {
+            usage: Some(ctx.usage.job_totals()),
+            ..ProgressEvent::new(
+                0,
+                ctx.finished_batches.load(Ordering::SeqCst),
+                ctx.total_batches.load(Ordering::SeqCst),
+                format!("💰 BUDGET REACHED ({}). Progress saved to {}.", reason, ctx.work_dir.display()),
+                false,
+            )
+        });
+        return Ok(JobOutcome::Budget(reason));
+    }
+
+    if ctx.stopped() {
+        // The journal keeps the job resumable; the snapshot is for reading.
+        save_temp_files(&ctx.work_dir, &ctx.files);
+    } else {
+        write_outputs(&ctx, &journal_path)?;
+    }
+
+    Ok(JobOutcome::Finished)
+}
+
+/// Fills the output of a resumed job from its journal and returns the `(file, line)`
+/// of the lines that are done. An entry only counts if the source line still has the
+/// same ID.
+fn replay_journal(files: &[SourceFile], memory: &TranslationMemory, journal_path: &Path) -> HashSet<(usize, usize)> {
+    let mut done = HashSet::new();
+    for ((file, line), entry) in journal::replay(journal_path) {
+        let Some(source) = files.get(file) else { continue };
+        let same_id = source
+            .raw_lines
+            .get(line)
+            .and_then(|l| l.split_once(":::"))
+            .is_some_and(|(id, _)| id.trim() == entry.id);
+        if line >= source.start_idx && same_id {
+            memory.insert(&source.prompts.target_lang, content_of(&source.raw_lines[line]), &entry.text);
+            source.output.lock().unwrap()[line] = entry.to_output();
+            source.done_lines.fetch_add(1, Ordering::SeqCst);
+            done.insert((file, line));
         }
-        batches.push(batch_items);
+    }
+    done
+}
 
-        // 5. Advance Cursor (By CORE amount only, to avoid infinite loops or skipping)
-        cursor = core_end; 
+/// Batches of all files, without the lines in `done`. Batches never span files; all
+/// files share one queue and worker pool. The languages of a source file share its plan.
+fn plan_batches(
+    files: &[SourceFile],
+    config: &TranslatorConfig,
+    limits: &SegmentLimits,
+    done: &HashSet<(usize, usize)>,
+) -> Result<Vec<Batch>, String> {
+    let sidecar = match config.segmentation {
+        Segmentation::Sidecar => segment::load_sidecar(&config.segment_metadata_path)?,
+        _ => HashMap::new(),
+    };
+    let mut batches: Vec<Batch> = Vec::new();
+    let mut plans: HashMap<&str, Vec<segment::Span>> = HashMap::new();
+    for (file, source) in files.iter().enumerate() {
+        // Lookback/lookahead lines are reference only; their own batch translates them.
+        let items = |range: std::ops::Range<usize>| range.map(|i| (i, source.raw_lines[i].clone())).collect::<Vec<_>>();
+        let spans = plans
+            .entry(source.path.as_str())
+            .or_insert_with(|| segment::plan(&source.raw_lines, source.start_idx, config.segmentation, &sidecar, limits));
+        batches.extend(
+            spans
+                .iter()
+                .cloned()
+                .map(|span| Batch {
+                    file,
+                    context_before: items(span.context_before),
+                    core: items(span.core).into_iter().filter(|(i, _)| !done.contains(&(file, *i))).collect(),
+                    context_after: items(span.context_after),
+                })
+                .filter(|batch| !batch.core.is_empty()),
+        );
     }
-    
-    let total_batches = batches.len();
-    let finished_batches = Arc::new(AtomicUsize::new(0));
-    
-    let stop_flag = state.stop_flag.clone();
-    let rate_limiter = state.rate_limiter.clone();
-    let config = Arc::new(config);
-    let output_mutex = Arc::new(Mutex::new(initial_output));
-    
-    let semaphore = Arc::new(tokio::sync::Semaphore::new(config.threads.max(1)));
-    let mut tasks = tokio::task::JoinSet::new();
+    Ok(batches)
+}
 
-    // Initial Status
-    let _ = app.emit("progress", ProgressEvent {
-        thread_id: 0,
-        current: 0,
-        total: total_batches,
-        message: format!("Started. {} Batches.", total_batches),
-        append: false,
-    });
-    
-    // Dispatch batches
-    for (i, batch) in batches.into_iter().enumerate() {
-        let global_thread_id = i + 1; // Thread 1, 2, 3...
-        
-        // Wait for worker slot. This blocks until a thread is free.
-        let permit = semaphore.clone().acquire_owned().await.map_err(|e| e.to_string())?;
-        
-        let batch_len = batch.len();
-        let start_line_content = &batch.first().map(|x| x.1.clone()).unwrap_or_default();
-        let end_line_content = &batch.last().map(|x| x.1.clone()).unwrap_or_default();
-
-        let start_id = start_line_content.split(":::").next().unwrap_or("?").trim();
-        let end_id = end_line_content.split(":::").next().unwrap_or("?").trim();
-        
-        log_thread_activity(global_thread_id, start_id, end_id);
-
-        let config = config.clone();
-        let stop_flag = stop_flag.clone();
-        let rate_limiter = rate_limiter.clone();
-        let app_handle = app.clone();
-        let output_mutex = output_mutex.clone();
-        let kill_signal = kill_signal.clone();
-        let finished_batches = finished_batches.clone();
-        
-        let start_id_owned = start_id.to_string();
-        let end_id_owned = end_id.to_string();
+/// Translates one batch, retrying until it succeeds, is split or the job stops.
+async fn run_batch(ctx: &JobContext, batch: Batch, thread_id: usize) {
+    let batch_len = batch.core.len();
+    let (start_id, end_id) = batch.id_range();
+    let source = &ctx.files[batch.file];
+    // With several files the IDs alone are ambiguous.
+    let file_label = if ctx.files.len() > 1 { format!("{} ", source.name()) } else { String::new() };
+    log_thread_activity(&ctx.work_dir, thread_id, &format!("{}{}", file_label, start_id), &end_id);
+    if ctx.stopped() {
+        return;
+    }
+    ctx.emit(ProgressEvent::new(thread_id, 0, batch_len, format!("Processing {}{}-{}", file_label, start_id, end_id), false));
 
-        tasks.spawn(async move {
-            let _permit = permit; // drop when finished
-            
-            if *stop_flag.lock().unwrap() { return; }
-            
-            let _ = app_handle.emit("progress", ProgressEvent {
-                thread_id: global_thread_id,
-                current: 0,
-                total: batch_len,
-                message: format!("Processing {}-{}", start_id_owned, end_id_owned),
-                append: false,
-            });
-
-            let client = reqwest::Client::new();
-            let batch_lines: Vec<String> = batch.iter().map(|(_, s)| s.clone()).collect();
-            let batch_indices: Vec<usize> = batch.iter().map(|(i, _)| *i).collect();
-
-            // Retry Loop
-            loop {
-                if *stop_flag.lock().unwrap() { break; }
-                
-                tokio::select! {
-                    _ = rate_limiter.wait(config.delay) => {},
-                    _ = kill_signal.notified() => { break; }
-                }
+    let pending = fill_from_memory(ctx, &batch, thread_id);
+    let batch_lines: Vec<String> = pending.iter().map(|(_, s)| s.clone()).collect();
+    let context_before: Vec<String> = batch.context_before.iter().map(|(_, s)| s.clone()).collect();
+    let context_after: Vec<String> = batch.context_after.iter().map(|(_, s)| s.clone()).collect();
+    let record_usage = |model: &str, u: &Usage| ctx.record_usage(&start_id, &end_id, model, u);
 
-                let result = tokio::select! {
-                     res = call_api_translate_with_result(
-                        &client, 
-                        &config, 
-                        &batch_lines, 
-                        &app_handle, 
-                        global_thread_id,
-                        batch_len
-                     ) => res,
-                     _ = kill_signal.notified() => { break; }
+    // Retry Loop
+    loop {
+        if ctx.stopped() {
+            return;
+        }
+        let mode = ctx.response_mode();
+        let mut started = Instant::now();
+        let result = if batch_lines.is_empty() {
+            // Everything came from the memory.
+            Ok(ApiResponse { lines: Vec::new(), usage: None, finish_reason: None, missing_tail: 0, extra: Vec::new(), confidence: HashMap::new(), sent: false })
+        } else {
+            tokio::select! {
+                _ = ctx.rate_limiter.wait() => {},
+                _ = ctx.kill_signal.notified() => { return; }
+            }
+            // Time spent queueing for the rate limiter is not provider latency.
+            started = Instant::now();
+            let request = BatchRequest {
+                lines: &batch_lines,
+                context_before: &context_before,
+                context_after: &context_after,
+                mode,
+                prompts: &source.prompts,
+                previous_lines: previous_lines(&source.raw_lines, &source.output.lock().unwrap(), source.start_idx, pending[0].0, ctx.config.context_lines),
+                model: &ctx.config.model,
+                source: None,
+            };
+            tokio::select! {
+                res = call_api_translate_with_result(&ctx.client, &ctx.config, request, &record_usage, &ctx.app, thread_id, batch_len) => res,
+                _ = ctx.kill_signal.notified() => { return; }
+            }
+        };
+        ctx.note_json_result(mode, &result);
+        ctx.note_latency(&result, started, batch_lines.len());
+
+        match result {
+            Ok(response) => {
+                finish_batch(ctx, batch, pending, response, mode, thread_id, &record_usage).await;
+                return;
+            }
+            Err(e) => {
+                let message = match e {
+                    ApiError::Stalled(_) => format!("⏳ {}. Retrying...", e),
+                    _ => format!("Error: {}. Retrying...", e),
                 };
-                
-                match result {
-                    Ok(translated) => {
-                        {
-                            let mut out = output_mutex.lock().unwrap();
-                            for (idx, text) in batch_indices.iter().zip(translated.iter()) {
-                                out[*idx] = text.clone();
-                            }
-                            save_temp_file(&out);
-                        }
-                         let _ = app_handle.emit("progress", ProgressEvent {
-                            thread_id: global_thread_id,
-                            current: batch_len,
-                            total: batch_len,
-                            message: "Done.".to_string(),
-                            append: false,
-                        });
-                        
-                        // Update Global Progress (Thread 0)
-                        let finished = finished_batches.fetch_add(1, Ordering::SeqCst) + 1;
-                        let _ = app_handle.emit("progress", ProgressEvent {
-                            thread_id: 0,
-                            current: finished,
-                            total: total_batches,
-                            message: format!("Progress: {}/{} Batches", finished, total_batches),
-                            append: false,
-                        });
-
-                        break; 
-                    }
-                    Err(e) => {
-                         let _ = app_handle.emit("progress", ProgressEvent {
-                            thread_id: global_thread_id,
-                            current: 0,
-                            total: batch_len,
-                            message: format!("Error: {}. Retrying...", e),
-                            append: true,
-                        });
-                        tokio::time::sleep(Duration::from_millis(1000)).await;
-                    }
+                ctx.emit(ProgressEvent { concurrency: ctx.adaptive_limit(), ..ProgressEvent::new(thread_id, 0, batch_len, message, true) });
+                tokio::time::sleep(Duration::from_millis(1000)).await;
+            }
+        }
+    }
+}
+
+/// Stores the lines of `batch` this job already translated into the same language and
+/// returns the ones that still have to be sent.
+fn fill_from_memory(ctx: &JobContext, batch: &Batch, thread_id: usize) -> Vec<(usize, String)> {
+    let source = &ctx.files[batch.file];
+    let mut pending: Vec<(usize, String)> = Vec::new();
+    let mut remembered: Vec<(usize, String)> = Vec::new();
+    for (idx, line) in &batch.core {
+        let hit = line
+            .split_once(":::")
+            .and_then(|(id, content)| ctx.memory.get(&source.prompts.target_lang, content).map(|t| format!("{}:::{}", id.trim(), t)));
+        match hit {
+            Some(output) => remembered.push((*idx, output)),
+            None => pending.push((*idx, line.clone())),
+        }
+    }
+    if !remembered.is_empty() {
+        score_lines(&mut ctx.record.lock().unwrap().files[batch.file], source, &remembered, &HashMap::new());
+        if let Err(e) = source.store(batch.file, &remembered, &ctx.journal) {
+            ctx.emit(ProgressEvent::new(thread_id, 0, batch.core.len(), format!("Journal write failed: {}", e), true));
+        }
+        emit_file_progress(&ctx.app, batch.file, source);
+    }
+    pending
+}
+
+/// Success path of a batch: split it if the output was cut off, otherwise keep the
+/// alternates, review, score and store the translation.
+async fn finish_batch(
+    ctx: &JobContext,
+    batch: Batch,
+    pending: Vec<(usize, String)>,
+    response: ApiResponse,
+    mode: ResponseMode,
+    thread_id: usize,
+    record_usage: &(dyn Fn(&str, &Usage) + Sync),
+) {
+    let batch_len = batch.core.len();
+    let file_index = batch.file;
+    let source = &ctx.files[file_index];
+    let mut job_usage = None;
+    let mut done_msg = "Done.".to_string();
+    if let Some(u) = &response.usage {
+        let cost = usage::cost_of(&ctx.config.prices, &ctx.config.model, u);
+        job_usage = Some(ctx.usage.job_totals());
+        done_msg = format!("Done. {} in / {} out tokens (${:.4})", u.prompt_tokens, u.completion_tokens, cost);
+    }
+    if (response.is_truncated() || response.missing_tail > 0) && pending.len() > 1 {
+        split_truncated(ctx, batch, pending, &response, thread_id);
+        return;
+    }
+    // Only the batch owning an ID writes it; anything else the model sent back is
+    // kept in the job record for review.
+    if !response.extra.is_empty() {
+        let (start_id, end_id) = batch.id_range();
+        let batch_name = format!("{}-{}", start_id, end_id);
+        let mut record = ctx.record.lock().unwrap();
+        for (id, text) in &response.extra {
+            record.files[file_index].add_alternate(id, &batch_name, text);
+        }
+    }
+    let mut translated: Vec<(usize, String)> = pending.iter().map(|(i, _)| *i).zip(response.lines.iter().cloned()).collect();
+    if let (Some(review_prompts), false) = (&source.review_prompts, pending.is_empty()) {
+        let sources: Vec<String> = pending.iter().map(|(_, s)| s.clone()).collect();
+        let Some(review) = review_batch(ctx, review_prompts, &sources, &response.lines, mode, thread_id, batch_len, record_usage).await else {
+            return;
+        };
+        match review {
+            Ok(review) => {
+                if review.usage.is_some() {
+                    job_usage = Some(ctx.usage.job_totals());
+                }
+                let corrected = apply_review(&mut ctx.record.lock().unwrap().files[file_index], &mut translated, &review.lines);
+                done_msg = format!("{} Reviewed: {} corrected.", done_msg, corrected);
+                if review.is_truncated() {
+                    done_msg = format!("{} Review truncated (finish_reason = length), the rest keeps its draft.", done_msg);
+                } else if review.missing_tail > 0 {
+                    done_msg = format!("{} Review skipped the last {} IDs, their drafts kept.", done_msg, review.missing_tail);
                 }
             }
-        });
+            Err(e) => {
+                done_msg = format!("{} Review failed ({}), draft kept.", done_msg, e);
+            }
+        }
+    }
+    score_lines(&mut ctx.record.lock().unwrap().files[file_index], source, &translated, &response.confidence);
+    done_msg.push_str(&store_translated(ctx, file_index, &pending, translated, thread_id, batch_len));
+    ctx.emit(ProgressEvent::new(thread_id, batch_len, batch_len, done_msg, false));
+    ctx.batch_finished(job_usage);
+}
+
+/// Hands both halves of a batch whose output was cut off back to the dispatcher,
+/// instead of letting the missing tail fall back to source text. Only the lines that
+/// were sent are split: the memory hits are stored already.
+fn split_truncated(ctx: &JobContext, batch: Batch, pending: Vec<(usize, String)>, response: &ApiResponse, thread_id: usize) {
+    let batch_len = batch.core.len();
+    let (first, second) = Batch { core: pending, ..batch }.split(ctx.max_context);
+    let reason = if response.is_truncated() {
+        "finish_reason = length".to_string()
+    } else {
+        format!("{} trailing IDs missing", response.missing_tail)
+    };
+    let msg = format!("✂ Output truncated ({}). Split into {} + {} lines.", reason, first.core.len(), second.core.len());
+    {
+        let mut q = ctx.queue.lock().unwrap();
+        q.push_front(second);
+        q.push_front(first);
     }
+    ctx.total_batches.fetch_add(1, Ordering::SeqCst);
+    ctx.emit(ProgressEvent::new(thread_id, batch_len, batch_len, msg, false));
+}
 
-    while let Some(_) = tasks.join_next().await {}
+/// Sends the drafts of a batch to the reviewer. Congestion is retried like the
+/// translation; any other error is returned and the draft kept. `None` if the job was
+/// killed meanwhile.
+#[allow(clippy::too_many_arguments)]
+async fn review_batch(
+    ctx: &JobContext,
+    prompts: &PromptTemplates,
+    sources: &[String],
+    drafts: &[String],
+    mode: ResponseMode,
+    thread_id: usize,
+    batch_len: usize,
+    record_usage: &(dyn Fn(&str, &Usage) + Sync),
+) -> Option<Result<ApiResponse, ApiError>> {
+    loop {
+        tokio::select! {
+            _ = ctx.rate_limiter.wait() => {},
+            _ = ctx.kill_signal.notified() => { return None; }
+        }
+        let request = BatchRequest {
+            lines: drafts,
+            context_before: &[],
+            context_after: &[],
+            mode,
+            prompts,
+            previous_lines: String::new(),
+            model: ctx.config.review_model(),
+            source: Some(sources),
+        };
+        let result = tokio::select! {
+            res = call_api_translate_with_result(&ctx.client, &ctx.config, request, record_usage, &ctx.app, thread_id, batch_len) => res,
+            _ = ctx.kill_signal.notified() => { return None; }
+        };
+        match result {
+            Err(e) if e.is_congestion() && !ctx.stopped() => {
+                if let Some(adaptive) = &ctx.adaptive {
+                    adaptive.on_congestion();
+                }
+                ctx.emit(ProgressEvent {
+                    concurrency: ctx.adaptive_limit(),
+                    ..ProgressEvent::new(thread_id, 0, batch_len, format!("Review error: {}. Retrying...", e), true)
+                });
+                tokio::time::sleep(Duration::from_millis(1000)).await;
+            }
+            result => return Some(result),
+        }
+    }
+}
 
-    if !*state.stop_flag.lock().unwrap() {
-        let final_lines = output_mutex.lock().unwrap();
-        let output_path = get_path("tran.txt");
-        let mut file = std::fs::File::create(output_path).map_err(|e| e.to_string())?;
-        for line in final_lines.iter() {
-            writeln!(file, "{}", line).map_err(|e| e.to_string())?;
+/// Applies the reviewer's answers to the drafts in `translated`. It answers OK or a
+/// correction per ID; IDs it left out keep their draft. Corrections replace the draft
+/// and both versions go to `job_file`. Returns the number of corrections.
+fn apply_review(job_file: &mut JobFile, translated: &mut [(usize, String)], reviewed: &[String]) -> usize {
+    let mut corrected = 0;
+    for ((_, line), reviewed) in translated.iter_mut().zip(reviewed) {
+        let (Some((id, draft)), Some((_, text))) = (line.split_once(":::"), reviewed.split_once(":::")) else { continue };
+        let text = text.trim();
+        if text.is_empty() || text.eq_ignore_ascii_case("ok") || text == draft.trim() {
+            continue;
         }
-        let _ = app.emit("progress", ProgressEvent {
-            thread_id: 0,
-            current: total_batches,
-            total: total_batches,
-            message: "Finished.".to_string(),
-            append: false,
-        });
+        job_file.add_review(id.trim(), draft, text);
+        *line = format!("{}:::{}", id.trim(), text);
+        corrected += 1;
     }
+    corrected
+}
 
+/// Stores the translated lines of a batch and returns the note for its done message.
+/// Lines that still read as source text stay out of the memory; with `Requeue` each
+/// gets one more try in a batch of its own.
+fn store_translated(
+    ctx: &JobContext,
+    file_index: usize,
+    pending: &[(usize, String)],
+    translated: Vec<(usize, String)>,
+    thread_id: usize,
+    batch_len: usize,
+) -> String {
+    let source = &ctx.files[file_index];
+    let untranslated = find_leftovers(source, &ctx.memory, pending, &translated);
+    let retry: Vec<(usize, String)> = if ctx.config.leftover_action == LeftoverAction::Requeue {
+        let mut seen = ctx.requeued.lock().unwrap();
+        untranslated.iter().filter(|(idx, _)| seen.insert((file_index, *idx))).cloned().collect()
+    } else {
+        Vec::new()
+    };
+    // Re-queued lines are not journaled or counted as done until their retry is, so a
+    // resumed job sends them again. Their draft stays in the output meanwhile.
+    let (held, kept): (Vec<_>, Vec<_>) = translated.into_iter().partition(|(idx, _)| retry.iter().any(|(r, _)| r == idx));
+    {
+        let mut out = source.output.lock().unwrap();
+        for (idx, text) in held {
+            out[idx] = text;
+        }
+    }
+    let stored = source.store(file_index, &kept, &ctx.journal);
+
+    let mut note = String::new();
+    if !untranslated.is_empty() {
+        note = format!(" {} lines look untranslated", untranslated.len());
+        if !retry.is_empty() {
+            note = format!("{}, {} re-queued", note, retry.len());
+            ctx.queue.lock().unwrap().push_back(Batch { file: file_index, context_before: Vec::new(), core: retry, context_after: Vec::new() });
+            ctx.total_batches.fetch_add(1, Ordering::SeqCst);
+        }
+        note.push('.');
+    }
+    emit_file_progress(&ctx.app, file_index, source);
+    if let Err(e) = stored {
+        ctx.emit(ProgressEvent::new(thread_id, batch_len, batch_len, format!("Journal write failed: {}", e), true));
+    }
+    note
+}
+
+/// Source lines whose translation still reads as source text. The others go into the
+/// memory for their repeats.
+fn find_leftovers(
+    source: &SourceFile,
+    memory: &TranslationMemory,
+    pending: &[(usize, String)],
+    translated: &[(usize, String)],
+) -> Vec<(usize, String)> {
+    let target_lang = &source.prompts.target_lang;
+    let mut untranslated = Vec::new();
+    for ((idx, line), (_, output)) in pending.iter().zip(translated) {
+        let (source_text, output_text) = (content_of(line), content_of(output));
+        if leftover::detect(source_text, output_text, &source.prompts.source_lang, target_lang).is_some() {
+            untranslated.push((*idx, line.clone()));
+        } else {
+            memory.insert(target_lang, source_text, output_text);
+        }
+    }
+    untranslated
+}
+
+/// Resolves the conflicts of the job record against the final output, saves it and
+/// reports what it holds for review.
+fn report_record(ctx: &JobContext) {
+    let mut record = ctx.record.lock().unwrap();
+    for (job_file, source) in record.files.iter_mut().zip(ctx.files.iter()) {
+        job_file.resolve(&source.output.lock().unwrap());
+    }
+    let _ = record.save(&ctx.work_dir);
+    let record_path = ctx.work_dir.join("job.json");
+    if record.conflict_count() > 0 {
+        ctx.emit(ProgressEvent::new(
+            0,
+            0,
+            0,
+            format!("{} IDs also came back from other batches; alternates saved to {}", record.conflict_count(), record_path.display()),
+            true,
+        ));
+    }
+    if record.review_count() > 0 {
+        ctx.emit(ProgressEvent::new(
+            0,
+            0,
+            0,
+            format!("{} lines corrected by review; drafts saved to {}", record.review_count(), record_path.display()),
+            true,
+        ));
+    }
+}
+
+/// Writes the output files of a completed job, backing up the ones it replaces, and
+/// drops the journal that is now compacted into them.
+fn write_outputs(ctx: &JobContext, journal_path: &Path) -> Result<(), String> {
+    let backups = data_dir(&ctx.app)?.join("backups");
+    for file in ctx.files.iter() {
+        storage::backup(&file.output_path, &backups)?;
+        let mut content = file.output.lock().unwrap().join("\n");
+        content.push('\n');
+        storage::write_atomic(&file.output_path, content.as_bytes())?;
+    }
+    {
+        let mut record = ctx.record.lock().unwrap();
+        record.finished = true;
+        record.save(&ctx.work_dir)?;
+    }
+    // Compacted into the output files; the journal is no longer needed.
+    let _ = std::fs::remove_file(journal_path);
+    let message = match ctx.files.len() {
+        1 => format!("Finished. Saved to {}", ctx.files[0].output_path.display()),
+        n => format!("Finished. {} files saved next to their sources.", n),
+    };
+    let total = ctx.total_batches.load(Ordering::SeqCst);
+    ctx.emit(ProgressEvent { usage: Some(ctx.usage.job_totals()), ..ProgressEvent::new(0, total, total, message, false) });
     Ok(())
 }
 
 async fn call_api_translate_with_result(
     client: &reqwest::Client,
     config: &TranslatorConfig,
-    lines: &[String],
+    request: BatchRequest<'_>,
+    record_usage: &(dyn Fn(&str, &Usage) + Sync),
     app: &AppHandle,
     thread_id: usize,
     total_in_chunk: usize,
-) -> Result<Vec<String>, String> {
+) -> Result<ApiResponse, ApiError> {
+    let BatchRequest { lines, context_before, context_after, mode, prompts, previous_lines, model, source } = request;
+
     // Filter for prompt: Only include lines with actual text content
     let prompt_lines: Vec<&str> = lines.iter()
         .filter(|line| {
@@ -517,17 +1905,41 @@ async fn call_api_translate_with_result(
 
     // If no content to translate, return early
     if prompt_lines.is_empty() {
-        return Ok(lines.to_vec());
+        return Ok(ApiResponse { lines: lines.to_vec(), usage: None, finish_reason: None, missing_tail: 0, extra: Vec::new(), confidence: HashMap::new(), sent: false });
     }
 
-    let prompt = prompt_lines.join("\n") + "\n\nREMINDER: Format 'ID:::TranslatedText'.";
+    let batch_text = prompt_lines.join("\n");
+    let context_before = context_text(context_before);
+    let context_after = context_text(context_after);
+    // Reviewed drafts are in the target language; glossary terms are found in the source.
+    let source_text = source
+        .map(|s| s.iter().filter(|l| !content_of(l).trim().is_empty()).cloned().collect::<Vec<_>>().join("\n"))
+        .unwrap_or_else(|| batch_text.clone());
+    let mut vars = HashMap::new();
+    vars.insert("source_lang", prompts.source_lang.clone());
+    vars.insert("target_lang", prompts.target_lang.clone());
+    vars.insert("glossary", prompts.glossary.render_for(&source_text));
+    vars.insert("source", source_text);
+    vars.insert("speaker", prompt::detect_speakers(prompt_lines.iter().copied()).join(", "));
+    vars.insert("previous_lines", previous_lines);
+    vars.insert("context", prompt::context_block(&context_before, &context_after));
+    vars.insert("context_before", context_before);
+    vars.insert("context_after", context_after);
+    let mut reminder = protocol::format_reminder(mode).to_string();
+    if config.self_confidence {
+        reminder = format!("{}\n{}", reminder, quality::CONFIDENCE_REMINDER);
+    }
+    vars.insert("format_reminder", reminder);
+    vars.insert("batch", batch_text);
+    let system_prompt = prompt::render(&prompts.system, &vars);
+    let prompt = prompt::render(&prompts.user, &vars);
     
     let url = format!("{}/chat/completions", config.base_url.trim_end_matches('/'));
     
     let mut payload = serde_json::json!({
-        "model": config.model,
+        "model": model,
         "messages": [
-            {"role": "system", "content": config.system_prompt},
+            {"role": "system", "content": system_prompt},
             {"role": "user", "content": prompt},
         ],
         "stream": config.stream
@@ -537,124 +1949,119 @@ async fn call_api_translate_with_result(
     if let Some(m) = config.max_tokens { payload["max_tokens"] = serde_json::json!(m); }
     if let Some(p) = config.top_p { payload["top_p"] = serde_json::json!(p); }
     if let Some(k) = config.top_k { payload["top_k"] = serde_json::json!(k); }
+    if config.stream && config.include_usage {
+        payload["stream_options"] = serde_json::json!({"include_usage": true});
+    }
+    protocol::apply_to_payload(mode, &mut payload);
 
     let resp = client.post(&url)
         .header("Authorization", format!("Bearer {}", config.api_key))
         .json(&payload)
         .send()
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
 
     if !resp.status().is_success() {
-        return Err(format!("API Status: {}", resp.status()));
+        let status = resp.status();
+        let body = resp.text().await.unwrap_or_default();
+        return Err(ApiError::Status(status, body));
     }
 
     let mut full_content = String::new();
+    let mut usage = None;
+    let mut finish_reason = None;
+    let mut saw_done = false;
 
     if config.stream {
         use futures_util::StreamExt;
         let mut stream = resp.bytes_stream();
-        let mut buffer = Vec::new();
-
-        while let Some(item) = stream.next().await {
-            let chunk = item.map_err(|e| e.to_string())?;
-            buffer.extend_from_slice(&chunk);
-            
-            if let Some(last_newline_idx) = buffer.iter().rposition(|&b| b == b'\n') {
-                let complete_chunk = buffer.drain(..=last_newline_idx).collect::<Vec<u8>>();
-                let s = String::from_utf8_lossy(&complete_chunk);
-                
-                for line in s.lines() {
-                    let line = line.trim();
-                    if line.starts_with("data: ") {
-                        let data = &line[6..];
-                        if data == "[DONE]" { break; }
-                        if let Ok(json) = serde_json::from_str::<serde_json::Value>(data) {
-                             if let Some(content) = json["choices"][0]["delta"]["content"].as_str() {
-                                 full_content.push_str(content);
-                                 let _ = app.emit("progress", ProgressEvent {
-                                    thread_id,
-                                    current: 0,
-                                    total: total_in_chunk,
-                                    message: content.to_string(),
-                                    append: true,
-                                 });
-                             }
-                        }
+        let mut decoder = SseDecoder::new();
+        let idle_timeout = timeout_from_secs(config.stream_idle_timeout);
+        let mut finished = false;
+
+        let streamed = async {
+            while !finished {
+                let item = match idle_timeout {
+                    Some(idle) => tokio::time::timeout(idle, stream.next())
+                        .await
+                        .map_err(|_| ApiError::Stalled(idle))?,
+                    None => stream.next().await,
+                };
+                let events = match item {
+                    Some(chunk) => decoder.feed(&chunk?),
+                    None => {
+                        finished = true;
+                        decoder.finish()
+                    }
+                };
+
+                for event in events {
+                    let delta = sse::parse_chat_event(&event).map_err(ApiError::Stream)?;
+                    if delta.done {
+                        saw_done = true;
+                        finished = true;
+                        break;
+                    }
+                    if let Some(u) = delta.usage { usage = Some(u); }
+                    if let Some(reason) = delta.finish_reason { finish_reason = Some(reason); }
+                    if let Some(content) = delta.content.or(delta.tool_arguments) {
+                        full_content.push_str(&content);
+                        let _ = app.emit("progress", ProgressEvent::new(thread_id, 0, total_in_chunk, content, true));
                     }
                 }
             }
+
+            // A connection that just drops looks like a normal end of stream;
+            // without [DONE] or a finish_reason the output may be cut short.
+            if !saw_done && finish_reason.is_none() {
+                return Err(ApiError::Stream("stream ended before completion".to_string()));
+            }
+            Ok(())
         }
-        
-        if !buffer.is_empty() {
-             let s = String::from_utf8_lossy(&buffer);
-             for line in s.lines() {
-                 let line = line.trim();
-                 if line.starts_with("data: ") {
-                    let data = &line[6..];
-                    if let Ok(json) = serde_json::from_str::<serde_json::Value>(data) {
-                        if let Some(content) = json["choices"][0]["delta"]["content"].as_str() {
-                            full_content.push_str(content);
-                        }
-                    }
-                 }
-             }
+        .await;
+        // The tokens were billed even if the stream then failed.
+        if let Some(u) = &usage {
+            record_usage(model, u);
         }
+        streamed?;
     } else {
-        let json: serde_json::Value = resp.json().await.map_err(|e| e.to_string())?;
-        if let Some(content) = json["choices"][0]["message"]["content"].as_str() {
-            full_content = content.to_string();
-            let _ = app.emit("progress", ProgressEvent {
+        let json: serde_json::Value = resp.json().await?;
+        usage = Usage::from_json(&json["usage"]);
+        if let Some(u) = &usage {
+            record_usage(model, u);
+        }
+        if let Some(msg) = sse::error_message(&json) {
+            return Err(ApiError::Stream(msg));
+        }
+        finish_reason = json["choices"][0]["finish_reason"].as_str().map(|s| s.to_string());
+        if let Some(content) = protocol::message_text(mode, &json["choices"][0]["message"]) {
+            full_content = content;
+            let _ = app.emit("progress", ProgressEvent::new(
                 thread_id,
-                current: 0,
-                total: total_in_chunk,
-                message: format!("Received {} chars", full_content.len()),
-                append: true,
-            });
+                0,
+                total_in_chunk,
+                format!("Received {} chars", full_content.len()),
+                true,
+            ));
         }
     }
 
-    // --- ROBUST PARSING LOGIC START ---
-    // Instead of simple split('\n'), we parse line by line and handle multi-line spills.
-    let raw_lines_out: Vec<&str> = full_content.split('\n').collect();
-    let mut translated_map = std::collections::HashMap::new();
-    
-    let mut current_id: Option<String> = None;
-    let mut current_text_buffer = String::new();
-
-    for line in raw_lines_out {
-        let trimmed = line.trim();
-        if trimmed.is_empty() { continue; }
-
-        // Check if line starts with ID:::
-        // Pattern: Starts with digits, then :::
-        let re_start = regex::Regex::new(r"^(\d+):::(.*)").unwrap();
-        
-        if let Some(caps) = re_start.captures(line) {
-            // FOUND NEW ID
-            // Save previous if exists
-            if let Some(cid) = current_id {
-                translated_map.insert(cid, current_text_buffer.trim().to_string());
-            }
+    let expected_ids: Vec<&str> = prompt_lines
+        .iter()
+        .filter_map(|l| l.split_once(":::").map(|(id, _)| id.trim()))
+        .collect();
 
-            // Start new
-            current_id = Some(caps[1].to_string());
-            current_text_buffer = caps[2].to_string();
-        } else {
-            // CONTINUATION LINE (AI added a newline)
-            // If we have an active ID, append this line to it using literal \n
-            if current_id.is_some() {
-                 current_text_buffer.push_str("\\n"); 
-                 current_text_buffer.push_str(trimmed);
-            }
+    let mut translated_map = if mode.is_json() {
+        match protocol::parse_json(&full_content) {
+            Ok(map) => map,
+            // Output cut off by max_tokens is never valid JSON; report it as a
+            // truncation so the batch gets split instead of retried as-is.
+            Err(_) if finish_reason.as_deref() == Some("length") => HashMap::new(),
+            Err(e) => return Err(ApiError::Format(e)),
         }
-    }
-    
-    // Save last buffer
-    if let Some(cid) = current_id {
-        translated_map.insert(cid, current_text_buffer.trim().to_string());
-    }
-    // --- ROBUST PARSING LOGIC END ---
+    } else {
+        protocol::parse_text(&full_content)
+    };
+    let confidence = if config.self_confidence { quality::take_confidence(&mut translated_map) } else { HashMap::new() };
 
     let mut new_results = Vec::new();
     for line in lines {
@@ -680,5 +2087,160 @@ async fn call_api_translate_with_result(
         }
     }
 
-    Ok(new_results)
+    // IDs missing only at the end are the signature of a response that was cut off.
+    let missing_tail = expected_ids
+        .iter()
+        .rev()
+        .take_while(|id| !translated_map.contains_key(**id))
+        .count();
+    // Nothing translated at all is a different failure; leave it to the normal fallback.
+    let missing_tail = if missing_tail == expected_ids.len() { 0 } else { missing_tail };
+
+    let mut extra: Vec<(String, String)> = translated_map
+        .into_iter()
+        .filter(|(id, _)| !expected_ids.contains(&id.as_str()))
+        .collect();
+    extra.sort();
+
+    Ok(ApiResponse { lines: new_results, usage, finish_reason, missing_tail, extra, confidence, sent: true })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(range: std::ops::Range<usize>) -> Vec<(usize, String)> {
+        range.map(|i| (i, format!("{}:::line {}", i, i))).collect()
+    }
+
+    fn indices(lines: &[(usize, String)]) -> Vec<usize> {
+        lines.iter().map(|(i, _)| *i).collect()
+    }
+
+    #[test]
+    fn falls_back_only_when_the_json_mode_is_rejected() {
+        let status = |code: u16, body: &str| ApiError::Status(reqwest::StatusCode::from_u16(code).unwrap(), body.to_string());
+        assert!(status(400, r#"{"error": {"message": "response_format json_schema is not supported"}}"#).rejects_json_mode());
+        assert!(status(422, "Unknown field: tools").rejects_json_mode());
+        assert!(!status(400, "This model's maximum context length is 8192 tokens").rejects_json_mode());
+        assert!(!status(404, "model `gpt-x` does not exist").rejects_json_mode());
+        assert!(!status(500, "tools exploded").rejects_json_mode());
+    }
+
+    #[test]
+    fn output_names_use_the_language_code() {
+        assert_eq!(default_output_path(Path::new("data/goc.txt"), "Vietnamese"), PathBuf::from("data/goc.vi.txt"));
+        assert_eq!(default_output_path(Path::new("goc.csv"), " Traditional Chinese "), PathBuf::from("goc.zh-tw.csv"));
+        assert_eq!(default_output_path(Path::new("goc"), "pt-BR"), PathBuf::from("goc.pt-br.txt"));
+    }
+
+    #[test]
+    fn unknown_languages_get_distinct_codes() {
+        assert_eq!(lang_code("Bahasa Indonesia"), "bahasa-indonesia");
+        assert_eq!(lang_code("Bahasa Melayu"), "bahasa-melayu");
+        assert_eq!(lang_code("Filipino (Tagalog)"), "filipino-tagalog");
+        assert_eq!(lang_code("tl"), "tl");
+        assert_eq!(lang_code("  "), "translated");
+        assert_eq!(lang_code("???"), "translated");
+    }
+
+    #[test]
+    fn plans_refuse_to_overwrite_inputs() {
+        let dir = std::env::temp_dir().join(format!("translator-test-{}-plan", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        for name in ["goc.txt", "goc.vi.txt"] {
+            std::fs::write(path(name), "1:::a").unwrap();
+        }
+        let vi = ["Vietnamese".to_string()];
+
+        let files = plan_files(&[path("goc.txt")], &vi, None).unwrap();
+        assert_eq!(files[0].output_file, path("goc.vi.txt"));
+        // goc.txt would overwrite goc.vi.txt, the other input.
+        let err = plan_files(&[path("goc.txt"), path("goc.vi.txt")], &vi, None).unwrap_err();
+        assert!(err.starts_with(&path("goc.vi.txt")), "{}", err);
+        let err = plan_files(&[path("goc.txt")], &vi, Some(dir.join(".").join("goc.txt").to_string_lossy().to_string())).unwrap_err();
+        assert!(err.contains("would be overwritten"), "{}", err);
+        let err = plan_files(&[path("goc.txt")], &["Vietnamese".to_string(), "vi".to_string()], None).unwrap_err();
+        assert!(err.starts_with("Two targets"), "{}", err);
+    }
+
+    #[test]
+    fn split_halves_the_core() {
+        let batch = Batch { file: 2, context_before: lines(0..2), core: lines(2..7), context_after: lines(7..9) };
+        let (first, second) = batch.split(2);
+        assert_eq!((first.file, second.file), (2, 2));
+        assert_eq!(indices(&first.core), vec![2, 3]);
+        assert_eq!(indices(&second.core), vec![4, 5, 6]);
+        assert_eq!(indices(&first.context_before), vec![0, 1]);
+        assert_eq!(indices(&second.context_after), vec![7, 8]);
+    }
+
+    #[test]
+    fn split_halves_see_each_other_as_context() {
+        let batch = Batch { file: 0, context_before: lines(0..2), core: lines(2..8), context_after: lines(8..10) };
+        let (first, second) = batch.split(2);
+        assert_eq!(indices(&first.context_after), vec![5, 6]);
+        assert_eq!(indices(&second.context_before), vec![3, 4]);
+
+        // A short half is topped up with the context beyond it.
+        let batch = Batch { file: 0, context_before: lines(0..3), core: lines(3..5), context_after: lines(5..8) };
+        let (first, second) = batch.split(3);
+        assert_eq!(indices(&first.context_after), vec![4, 5, 6]);
+        assert_eq!(indices(&second.context_before), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn split_adds_inner_context_even_without_outer_context() {
+        let batch = Batch { file: 0, context_before: Vec::new(), core: lines(0..6), context_after: Vec::new() };
+        let (first, second) = batch.split(2);
+        assert!(first.context_before.is_empty());
+        assert_eq!(indices(&first.context_after), vec![3, 4]);
+        assert_eq!(indices(&second.context_before), vec![1, 2]);
+        assert!(second.context_after.is_empty());
+    }
+
+    #[test]
+    fn repeated_splits_reach_single_lines() {
+        let mut queue = VecDeque::from([Batch { file: 0, context_before: Vec::new(), core: lines(0..5), context_after: Vec::new() }]);
+        let mut done = Vec::new();
+        while let Some(batch) = queue.pop_front() {
+            if batch.core.len() > 1 {
+                let (first, second) = batch.split(1);
+                queue.push_front(second);
+                queue.push_front(first);
+            } else {
+                done.extend(indices(&batch.core));
+            }
+        }
+        // Every line comes out exactly once and in order.
+        assert_eq!(done, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn id_range_names_the_first_and_last_core_line() {
+        let batch = Batch { file: 0, context_before: lines(0..2), core: lines(2..5), context_after: lines(5..6) };
+        assert_eq!(batch.id_range(), ("2".to_string(), "4".to_string()));
+    }
+
+    #[test]
+    fn review_replaces_only_corrected_drafts() {
+        let mut job_file = JobFile::new("goc.txt", "goc.vi.txt");
+        let mut translated: Vec<(usize, String)> =
+            ["1:::Xin chao", "2:::Tam biet", "3:::Cam on", "4:::Vang"].iter().map(|l| l.to_string()).enumerate().collect();
+        // OK, a correction, the draft unchanged, nothing; line 4 left out entirely.
+        let reviewed = ["1:::OK", "2:::Tạm biệt", "3::: Cam on ", "4:::"].map(String::from);
+        let corrected = apply_review(&mut job_file, &mut translated[..3], &reviewed[..3]);
+        assert_eq!(corrected, 1);
+        let lines: Vec<&str> = translated.iter().map(|(_, l)| l.as_str()).collect();
+        assert_eq!(lines, vec!["1:::Xin chao", "2:::Tạm biệt", "3:::Cam on", "4:::Vang"]);
+        assert_eq!(job_file.reviews.len(), 1);
+        assert_eq!(job_file.reviews["2"].draft, "Tam biet");
+        assert_eq!(job_file.reviews["2"].reviewed, "Tạm biệt");
+
+        // An empty answer keeps the draft too.
+        assert_eq!(apply_review(&mut job_file, &mut translated[3..], &reviewed[3..]), 0);
+        assert_eq!(translated[3].1, "4:::Vang");
+    }
 }
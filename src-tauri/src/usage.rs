@@ -0,0 +1,382 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Token counts reported by the provider in the `usage` object.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl Usage {
+    /// Reads an OpenAI-style `usage` object. Returns `None` when it is missing or null
+    /// (streams only carry it on the final chunk).
+    pub fn from_json(value: &serde_json::Value) -> Option<Self> {
+        let obj = value.as_object()?;
+        let prompt = obj.get("prompt_tokens").and_then(|v| v.as_u64()).unwrap_or(0);
+        let completion = obj.get("completion_tokens").and_then(|v| v.as_u64()).unwrap_or(0);
+        if prompt == 0 && completion == 0 {
+            return None;
+        }
+        Some(Self { prompt_tokens: prompt, completion_tokens: completion })
+    }
+}
+
+/// Price of a model in USD per 1M tokens.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// Looks up the price of `model`, falling back to the `"*"` entry if present.
+pub fn cost_of(prices: &HashMap<String, ModelPrice>, model: &str, usage: &Usage) -> f64 {
+    match prices.get(model).or_else(|| prices.get("*")) {
+        Some(p) => {
+            (usage.prompt_tokens as f64 * p.input + usage.completion_tokens as f64 * p.output) / 1_000_000.0
+        }
+        None => 0.0,
+    }
+}
+
+/// Provider name used for aggregation: the host of the base URL.
+pub fn provider_of(base_url: &str) -> String {
+    reqwest::Url::parse(base_url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_string()))
+        .unwrap_or_else(|| base_url.to_string())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, usage: &Usage, cost: f64) {
+        self.requests += 1;
+        self.prompt_tokens += usage.prompt_tokens;
+        self.completion_tokens += usage.completion_tokens;
+        self.cost += cost;
+    }
+
+    fn merge(&mut self, other: &UsageTotals) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost += other.cost;
+    }
+
+    fn tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// One line of `usage_log.jsonl`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageRecord {
+    pub timestamp: String,
+    pub job_id: String,
+    pub provider: String,
+    pub model: String,
+    pub start_id: String,
+    pub end_id: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+}
+
+/// Running totals of a single job. Per-provider and per-model figures come from
+/// the usage log (see [`summarize`]).
+#[derive(Default)]
+pub struct UsageTracker {
+    /// Spent by earlier runs of a resumed job.
+    earlier: UsageTotals,
+    run: Mutex<UsageTotals>,
+}
+
+impl UsageTracker {
+    /// Tracker of a resumed job, starting from what its earlier runs spent.
+    pub fn resume(earlier: UsageTotals) -> Self {
+        Self { earlier, ..Self::default() }
+    }
+
+    /// Adds a response and returns the job totals.
+    pub fn record(&self, usage: &Usage, cost: f64) -> UsageTotals {
+        self.run.lock().unwrap().add(usage, cost);
+        self.job_totals()
+    }
+
+    /// Totals of the job, earlier runs included.
+    pub fn job_totals(&self) -> UsageTotals {
+        let mut totals = self.earlier.clone();
+        totals.merge(&self.run.lock().unwrap());
+        totals
+    }
+
+    /// Totals of this run only.
+    pub fn run_totals(&self) -> UsageTotals {
+        self.run.lock().unwrap().clone()
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct UsageSummary {
+    pub total: UsageTotals,
+    pub by_provider: HashMap<String, UsageTotals>,
+    pub by_model: HashMap<String, UsageTotals>,
+}
+
+pub fn append_record(path: &Path, record: &UsageRecord) {
+    if let Ok(line) = serde_json::to_string(record) {
+        if let Ok(mut file) = std::fs::OpenOptions::new().create(true).append(true).open(path) {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+}
+
+pub fn read_records(path: &Path) -> Vec<UsageRecord> {
+    std::fs::read_to_string(path)
+        .map(|content| {
+            content
+                .lines()
+                .filter_map(|l| serde_json::from_str::<UsageRecord>(l).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn summarize(records: &[UsageRecord]) -> UsageSummary {
+    let mut summary = UsageSummary::default();
+    for r in records {
+        let usage = Usage { prompt_tokens: r.prompt_tokens, completion_tokens: r.completion_tokens };
+        summary.total.add(&usage, r.cost);
+        summary.by_provider.entry(r.provider.clone()).or_default().add(&usage, r.cost);
+        summary.by_model.entry(r.model.clone()).or_default().add(&usage, r.cost);
+    }
+    summary
+}
+
+/// Totals of the records of one job, over all of its runs.
+pub fn job_totals(records: &[UsageRecord], job_id: &str) -> UsageTotals {
+    let mut totals = UsageTotals::default();
+    for r in records.iter().filter(|r| r.job_id == job_id) {
+        let usage = Usage { prompt_tokens: r.prompt_tokens, completion_tokens: r.completion_tokens };
+        totals.add(&usage, r.cost);
+    }
+    totals
+}
+
+/// Totals of the records logged on `day` (local date, `YYYY-MM-DD`).
+pub fn day_totals(records: &[UsageRecord], day: &str) -> UsageTotals {
+    let mut totals = UsageTotals::default();
+    for r in records.iter().filter(|r| r.timestamp.starts_with(day)) {
+        let usage = Usage { prompt_tokens: r.prompt_tokens, completion_tokens: r.completion_tokens };
+        totals.add(&usage, r.cost);
+    }
+    totals
+}
+
+/// Spending caps taken from the config. `None` means unlimited.
+pub struct BudgetLimits {
+    pub max_job_cost: Option<f64>,
+    pub max_job_tokens: Option<u64>,
+    pub max_daily_cost: Option<f64>,
+    pub max_daily_tokens: Option<u64>,
+}
+
+impl BudgetLimits {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_job_cost.is_none()
+            && self.max_job_tokens.is_none()
+            && self.max_daily_cost.is_none()
+            && self.max_daily_tokens.is_none()
+    }
+
+    /// Returns the reason if any limit is crossed. `job` covers every run of the job,
+    /// `run` only the current one, which is added to what was `spent_today` before it
+    /// started (earlier runs of a resumed job are already in there).
+    pub fn exceeded(&self, job: &UsageTotals, run: &UsageTotals, spent_today: &UsageTotals) -> Option<String> {
+        let job_tokens = job.tokens();
+        let day_tokens = spent_today.tokens() + run.tokens();
+        let day_cost = spent_today.cost + run.cost;
+
+        if let Some(max) = self.max_job_cost {
+            if job.cost >= max {
+                return Some(format!("job cost ${:.4} >= ${:.4}", job.cost, max));
+            }
+        }
+        if let Some(max) = self.max_job_tokens {
+            if job_tokens >= max {
+                return Some(format!("job tokens {} >= {}", job_tokens, max));
+            }
+        }
+        if let Some(max) = self.max_daily_cost {
+            if day_cost >= max {
+                return Some(format!("daily cost ${:.4} >= ${:.4}", day_cost, max));
+            }
+        }
+        if let Some(max) = self.max_daily_tokens {
+            if day_tokens >= max {
+                return Some(format!("daily tokens {} >= {}", day_tokens, max));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(provider: &str, model: &str, prompt: u64, completion: u64, cost: f64) -> UsageRecord {
+        UsageRecord {
+            timestamp: "2024-05-01T10:00:00+07:00".to_string(),
+            job_id: "job".to_string(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            start_id: "1".to_string(),
+            end_id: "2".to_string(),
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+            cost,
+        }
+    }
+
+    #[test]
+    fn reads_usage_object() {
+        let usage = Usage::from_json(&serde_json::json!({"prompt_tokens": 120, "completion_tokens": 30, "total_tokens": 150}));
+        assert_eq!(usage, Some(Usage { prompt_tokens: 120, completion_tokens: 30 }));
+    }
+
+    #[test]
+    fn missing_null_or_zero_usage_is_none() {
+        assert_eq!(Usage::from_json(&serde_json::Value::Null), None);
+        assert_eq!(Usage::from_json(&serde_json::json!({"prompt_tokens": 0, "completion_tokens": 0})), None);
+        assert_eq!(
+            Usage::from_json(&serde_json::json!({"completion_tokens": 5})),
+            Some(Usage { prompt_tokens: 0, completion_tokens: 5 })
+        );
+    }
+
+    #[test]
+    fn cost_uses_model_price_then_wildcard() {
+        let mut prices = HashMap::new();
+        prices.insert("gpt".to_string(), ModelPrice { input: 2.0, output: 8.0 });
+        let usage = Usage { prompt_tokens: 500_000, completion_tokens: 250_000 };
+        assert!((cost_of(&prices, "gpt", &usage) - 3.0).abs() < 1e-9);
+        assert_eq!(cost_of(&prices, "other", &usage), 0.0);
+
+        prices.insert("*".to_string(), ModelPrice { input: 1.0, output: 1.0 });
+        assert!((cost_of(&prices, "other", &usage) - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn summarizes_by_provider_and_model() {
+        let records = [
+            record("api.openai.com", "gpt", 100, 10, 0.5),
+            record("api.openai.com", "mini", 50, 5, 0.25),
+            record("openrouter.ai", "gpt", 10, 1, 0.125),
+        ];
+        let summary = summarize(&records);
+        assert_eq!(summary.total.requests, 3);
+        assert_eq!(summary.total.prompt_tokens, 160);
+        assert_eq!(summary.total.completion_tokens, 16);
+        assert!((summary.total.cost - 0.875).abs() < 1e-9);
+        assert_eq!(summary.by_provider["api.openai.com"].requests, 2);
+        assert_eq!(summary.by_provider["openrouter.ai"].prompt_tokens, 10);
+        assert_eq!(summary.by_model["gpt"].completion_tokens, 11);
+        assert!((summary.by_model["mini"].cost - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn tracker_accumulates_job_totals() {
+        let tracker = UsageTracker::default();
+        tracker.record(&Usage { prompt_tokens: 10, completion_tokens: 2 }, 0.5);
+        let totals = tracker.record(&Usage { prompt_tokens: 5, completion_tokens: 1 }, 0.25);
+        assert_eq!(totals.requests, 2);
+        assert_eq!(totals.prompt_tokens, 15);
+        assert_eq!(tracker.job_totals().completion_tokens, 3);
+    }
+
+    #[test]
+    fn resumed_tracker_starts_from_the_logged_job_totals() {
+        let mut other = record("api.openai.com", "gpt", 1000, 1000, 9.0);
+        other.job_id = "other".to_string();
+        let records = [record("api.openai.com", "gpt", 100, 10, 0.5), other, record("api.openai.com", "gpt", 50, 5, 0.25)];
+        let earlier = job_totals(&records, "job");
+        assert_eq!((earlier.requests, earlier.tokens()), (2, 165));
+
+        let tracker = UsageTracker::resume(earlier);
+        let totals = tracker.record(&Usage { prompt_tokens: 10, completion_tokens: 0 }, 0.125);
+        assert_eq!(totals.tokens(), 175);
+        assert!((totals.cost - 0.875).abs() < 1e-9);
+        assert_eq!(tracker.run_totals().tokens(), 10);
+    }
+
+    fn totals(tokens: u64, cost: f64) -> UsageTotals {
+        UsageTotals { requests: 1, prompt_tokens: tokens, completion_tokens: 0, cost }
+    }
+
+    fn limits() -> BudgetLimits {
+        BudgetLimits { max_job_cost: None, max_job_tokens: None, max_daily_cost: None, max_daily_tokens: None }
+    }
+
+    #[test]
+    fn each_limit_stops_at_its_cap() {
+        let none = UsageTotals::default();
+        assert!(limits().is_unlimited());
+        assert_eq!(limits().exceeded(&totals(1_000_000, 100.0), &totals(1_000_000, 100.0), &none), None);
+
+        let job_cost = BudgetLimits { max_job_cost: Some(1.0), ..limits() };
+        assert_eq!(job_cost.exceeded(&totals(0, 0.99), &none, &none), None);
+        assert_eq!(job_cost.exceeded(&totals(0, 1.0), &none, &none), Some("job cost $1.0000 >= $1.0000".to_string()));
+
+        let job_tokens = BudgetLimits { max_job_tokens: Some(100), ..limits() };
+        assert_eq!(job_tokens.exceeded(&totals(99, 0.0), &none, &none), None);
+        assert_eq!(job_tokens.exceeded(&totals(100, 0.0), &none, &none), Some("job tokens 100 >= 100".to_string()));
+
+        let daily_cost = BudgetLimits { max_daily_cost: Some(2.0), ..limits() };
+        assert_eq!(daily_cost.exceeded(&none, &totals(0, 1.5), &none), None);
+        assert_eq!(daily_cost.exceeded(&none, &totals(0, 2.0), &none), Some("daily cost $2.0000 >= $2.0000".to_string()));
+
+        let daily_tokens = BudgetLimits { max_daily_tokens: Some(500), ..limits() };
+        assert_eq!(daily_tokens.exceeded(&none, &totals(499, 0.0), &none), None);
+        assert_eq!(daily_tokens.exceeded(&none, &totals(500, 0.0), &none), Some("daily tokens 500 >= 500".to_string()));
+    }
+
+    #[test]
+    fn daily_limits_add_the_run_to_what_was_spent_today() {
+        let limits = BudgetLimits { max_daily_cost: Some(2.0), max_daily_tokens: Some(500), ..limits() };
+        assert_eq!(limits.exceeded(&totals(100, 0.5), &totals(100, 0.5), &totals(300, 1.0)), None);
+        assert_eq!(
+            limits.exceeded(&totals(200, 0.5), &totals(200, 0.5), &totals(300, 1.0)),
+            Some("daily tokens 500 >= 500".to_string())
+        );
+        assert_eq!(
+            limits.exceeded(&totals(100, 1.0), &totals(100, 1.0), &totals(300, 1.0)),
+            Some("daily cost $2.0000 >= $2.0000".to_string())
+        );
+        // What earlier runs of the job spent is in `job` and, if it was today, in
+        // `spent_today`; it is not counted twice.
+        assert_eq!(limits.exceeded(&totals(400, 1.5), &totals(100, 0.5), &totals(300, 1.0)), None);
+    }
+
+    #[test]
+    fn day_totals_only_count_that_day() {
+        let mut yesterday = record("api.openai.com", "gpt", 1000, 0, 5.0);
+        yesterday.timestamp = "2024-04-30T23:59:59+07:00".to_string();
+        let records = [record("api.openai.com", "gpt", 100, 10, 0.5), yesterday, record("openrouter.ai", "mini", 20, 0, 0.25)];
+        let today = day_totals(&records, "2024-05-01");
+        assert_eq!((today.requests, today.tokens()), (2, 130));
+        assert!((today.cost - 0.75).abs() < 1e-9);
+        assert_eq!(day_totals(&records, "2024-04-30").prompt_tokens, 1000);
+        assert_eq!(day_totals(&records, "2024-05-02").requests, 0);
+    }
+}
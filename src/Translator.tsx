@@ -1,7 +1,7 @@
 import { createSignal, createEffect, For, Show, onMount, onCleanup } from "solid-js";
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
-import { open } from "@tauri-apps/plugin-dialog";
+import { open, save } from "@tauri-apps/plugin-dialog";
 import logo from "./assets/logo.png";
 
 interface TranslatorConfig {
@@ -18,6 +18,106 @@ interface TranslatorConfig {
   batch_size: number;
   delay: number;
   last_file: string;
+  include_usage?: boolean;
+  prices?: Record<string, { input: number; output: number }>;
+  max_job_cost?: number | null;
+  max_job_tokens?: number | null;
+  max_daily_cost?: number | null;
+  max_daily_tokens?: number | null;
+  adaptive_threads?: boolean;
+  max_threads?: number | null;
+  connect_timeout?: number | null;
+  request_timeout?: number | null;
+  stream_idle_timeout?: number | null;
+  proxy_url?: string;
+  ca_bundle_path?: string;
+  extra_headers?: Record<string, string>;
+  user_agent?: string;
+  response_mode?: "text" | "json_schema" | "json_object" | "tool";
+  source_lang?: string;
+  target_lang?: string;
+  prompt_preset?: string;
+  system_template?: string;
+  user_template?: string;
+  glossary_path?: string;
+  context_lines?: number;
+  segmentation?: "overlap" | "id_gap" | "scene" | "tags" | "sidecar";
+  segment_metadata_path?: string;
+  targets?: TargetLanguage[];
+  review?: boolean;
+  review_model?: string;
+  review_template?: string;
+  self_confidence?: boolean;
+  leftover_action?: "flag" | "requeue";
+}
+
+interface TargetLanguage {
+  target_lang: string;
+  prompt_preset?: string;
+  system_template?: string;
+  glossary_path?: string;
+}
+
+interface UsageTotals {
+  requests: number;
+  prompt_tokens: number;
+  completion_tokens: number;
+  cost: number;
+}
+
+interface PromptPreset {
+  name: string;
+  file: string;
+  game: string | null;
+  source_lang: string | null;
+  target_lang: string | null;
+  model: string | null;
+  temperature: number | null;
+  description: string | null;
+}
+
+interface JobFile {
+  source_file: string;
+  output_file: string;
+  target_lang?: string;
+}
+
+interface JobRecord {
+  job_id: string;
+  started_at: string;
+  finished: boolean;
+  files: JobFile[];
+}
+
+interface QueuedJob {
+  id: string;
+  added_at: string;
+  config: TranslatorConfig;
+  file_paths: string[];
+  output_path: string | null;
+  status: { state: "pending" | "running" | "done" | "stopped" | "failed"; detail?: string };
+}
+
+interface PatchReport {
+  job_id: string;
+  old_source: string;
+  old_translation: string;
+  new_source: string;
+  output_file: string;
+  unchanged: number;
+  modified: string[];
+  new: string[];
+  removed: string[];
+  untranslated: string[];
+}
+
+interface FileProgressEvent {
+  file_index: number;
+  path: string;
+  output_path: string;
+  target_lang: string;
+  done: number;
+  total: number;
 }
 
 interface ProgressEvent {
@@ -26,6 +126,8 @@ interface ProgressEvent {
   total: number;
   message: string;
   append: boolean;
+  usage?: UsageTotals;
+  concurrency?: number;
 }
 
 const DEFAULT_SYSTEM_PROMPT = `# ROLE: Master of Game Localization (English to Vietnamese)
@@ -59,12 +161,24 @@ export default function Translator() {
     batch_size: 50,
     delay: 1.3,
     last_file: "",
+    connect_timeout: 20,
+    stream_idle_timeout: 60,
   });
 
   const [models, setModels] = createSignal<string[]>([]);
+  const [templates, setTemplates] = createSignal<string[]>([]);
+  const [presets, setPresets] = createSignal<PromptPreset[]>([]);
+  // Empty: next to the input, named after the target language (goc.vi.txt).
+  const [outputPath, setOutputPath] = createSignal("");
   const [isRunning, setIsRunning] = createSignal(false);
+  const [isPaused, setIsPaused] = createSignal(false);
   const [progress, setProgress] = createSignal<Record<number, ProgressEvent>>({});
   const [threadLogs, setThreadLogs] = createSignal<Record<number, string>>({});
+  const [jobUsage, setJobUsage] = createSignal<UsageTotals | null>(null);
+  const [fileProgress, setFileProgress] = createSignal<Record<number, FileProgressEvent>>({});
+  const [jobQueue, setJobQueue] = createSignal<QueuedJob[]>([]);
+  const [patchFiles, setPatchFiles] = createSignal({ old_source: "", old_translation: "", new_source: "" });
+  const [patchReport, setPatchReport] = createSignal<PatchReport | null>(null);
 
   const [showSettings, setShowSettings] = createSignal(false);
   const [monitorThreadId, setMonitorThreadId] = createSignal<number | null>(null);
@@ -90,6 +204,10 @@ export default function Translator() {
       console.error("Failed to load config from file", e);
     }
 
+    invoke<string[]>("list_prompt_templates").then(setTemplates).catch(e => console.error(e));
+    invoke<PromptPreset[]>("list_prompt_presets").then(setPresets).catch(e => console.error(e));
+    invoke<QueuedJob[]>("list_queue").then(setJobQueue).catch(e => console.error(e));
+
     const handleClickOutside = (e: MouseEvent) => {
       if (modelDropdownRef && !modelDropdownRef.contains(e.target as Node)) {
         setIsModelDropdownOpen(false);
@@ -109,6 +227,7 @@ export default function Translator() {
     const unlistenPromise = listen<ProgressEvent>("progress", (event) => {
       const p = event.payload;
       setProgress((prev) => ({ ...prev, [p.thread_id]: p }));
+      if (p.usage) setJobUsage(p.usage);
       if (p.message) {
         setThreadLogs((prev) => {
           const oldLog = prev[p.thread_id] || "";
@@ -122,22 +241,77 @@ export default function Translator() {
     };
   });
 
+  createEffect(() => {
+    const unlistenPromise = listen<QueuedJob[]>("queue", (event) => setJobQueue(event.payload));
+    return () => {
+      unlistenPromise.then((f) => f());
+    };
+  });
+
+  createEffect(() => {
+    const unlistenPromise = listen<FileProgressEvent>("file_progress", (event) => {
+      setFileProgress((prev) => ({ ...prev, [event.payload.file_index]: event.payload }));
+    });
+    return () => {
+      unlistenPromise.then((f) => f());
+    };
+  });
+
+  // `last_file` holds one or more files, directories or globs separated by ";".
+  const inputFiles = () => config().last_file.split(";").map(f => f.trim()).filter(f => f);
+  // An explicit output file only applies to one input translated into one language.
+  const isSingleOutput = () =>
+    inputFiles().length === 1 && !/[*?[]/.test(inputFiles()[0]) && (config().targets ?? []).length <= 1;
+  const setTarget = (index: number, patch: Partial<TargetLanguage>) =>
+    setConfig({ ...config(), targets: (config().targets ?? []).map((t, i) => (i === index ? { ...t, ...patch } : t)) });
+
   const handleFileSelect = async () => {
     const selected = await open({
-      multiple: false,
+      multiple: true,
       filters: [{ name: "Text", extensions: ["txt", "csv"] }],
     });
     if (selected) {
-      setConfig({ ...config(), last_file: selected as string });
+      const list = Array.isArray(selected) ? selected : [selected];
+      setConfig({ ...config(), last_file: list.join("; ") });
+      setOutputPath("");
+    }
+  };
+
+  const handleOutputSelect = async () => {
+    const selected = await save({
+      filters: [{ name: "Text", extensions: ["txt", "csv"] }],
+    });
+    if (selected) {
+      setOutputPath(selected);
+    }
+  };
+
+  // Picking a preset also applies its recommended model, temperature and language pair.
+  const selectPreset = (name: string) => {
+    const preset = presets().find(p => p.name === name);
+    setConfig(prev => ({
+      ...prev,
+      prompt_preset: name,
+      ...(preset?.model ? { model: preset.model } : {}),
+      ...(preset?.temperature != null ? { temperature: preset.temperature } : {}),
+      ...(preset?.source_lang ? { source_lang: preset.source_lang } : {}),
+      ...(preset?.target_lang ? { target_lang: preset.target_lang } : {}),
+    }));
+  };
+
+  const handleGlossarySelect = async () => {
+    const selected = await open({
+      multiple: false,
+      filters: [{ name: "Glossary", extensions: ["txt", "tsv"] }],
+    });
+    if (selected) {
+      setConfig({ ...config(), glossary_path: selected as string });
     }
   };
 
   const fetchModels = async () => {
     try {
-      const res = await invoke<string[]>("fetch_models", {
-        baseUrl: config().base_url,
-        apiKey: config().api_key,
-      });
+      const res = await invoke<string[]>("fetch_models", { config: config() });
       setModels(res);
       if (!config().model && res.length > 0) {
         setConfig(prev => ({ ...prev, model: res[0] }));
@@ -148,24 +322,154 @@ export default function Translator() {
     }
   };
 
-  const startTranslation = async () => {
+  const startTranslation = async (resumeJobId: string | null = null) => {
+    if (!config().last_file) {
+      alert("Please select a file first.");
+      return;
+    }
+    setIsRunning(true);
+    setProgress({});
+    setThreadLogs({});
+    setJobUsage(null);
+    setFileProgress({});
+    try {
+      await invoke("start_translation", {
+        config: config(),
+        filePaths: inputFiles(),
+        outputPath: isSingleOutput() ? outputPath() || null : null,
+        resumeJobId,
+      });
+      alert("Translation finished!");
+    } catch (e) {
+      alert(`Error: ${e}`);
+    } finally {
+      setIsRunning(false);
+      setIsPaused(false);
+    }
+  };
+
+  // Snapshot of the current settings and files, run later by RUN QUEUE.
+  const enqueueJob = async () => {
     if (!config().last_file) {
       alert("Please select a file first.");
       return;
     }
+    try {
+      await invoke("enqueue_job", {
+        config: config(),
+        filePaths: inputFiles(),
+        outputPath: isSingleOutput() ? outputPath() || null : null,
+      });
+    } catch (e) {
+      alert(`Error: ${e}`);
+    }
+  };
+
+  const pickPatchFile = async (key: "old_source" | "old_translation" | "new_source") => {
+    const selected = await open({
+      multiple: false,
+      filters: [{ name: "Text", extensions: ["txt", "csv"] }],
+    });
+    if (selected) {
+      setPatchFiles({ ...patchFiles(), [key]: selected as string });
+      setPatchReport(null);
+    }
+  };
+
+  // Diffs the old and new source; unchanged lines keep their old translation.
+  const preparePatch = async () => {
+    try {
+      const report = await invoke<PatchReport>("prepare_patch_job", {
+        config: config(),
+        oldSource: patchFiles().old_source,
+        oldTranslation: patchFiles().old_translation,
+        newSource: patchFiles().new_source,
+        outputPath: null,
+      });
+      setPatchReport(report);
+    } catch (e) {
+      alert(`Error: ${e}`);
+    }
+  };
+
+  const runPatch = async () => {
+    const report = patchReport();
+    if (!report) return;
     setIsRunning(true);
     setProgress({});
     setThreadLogs({});
+    setJobUsage(null);
+    setFileProgress({});
     try {
       await invoke("start_translation", {
         config: config(),
-        filePath: config().last_file,
+        filePaths: [report.new_source],
+        outputPath: null,
+        resumeJobId: report.job_id,
       });
+      setPatchReport(null);
       alert("Translation finished!");
     } catch (e) {
       alert(`Error: ${e}`);
     } finally {
       setIsRunning(false);
+      setIsPaused(false);
+    }
+  };
+
+  const runQueue = async () => {
+    setIsRunning(true);
+    setProgress({});
+    setThreadLogs({});
+    setJobUsage(null);
+    setFileProgress({});
+    try {
+      await invoke("run_queue");
+    } catch (e) {
+      alert(`Error: ${e}`);
+    } finally {
+      setIsRunning(false);
+      setIsPaused(false);
+    }
+  };
+
+  const removeQueuedJob = async (id: string) => {
+    try {
+      await invoke("remove_queued_job", { id });
+    } catch (e) {
+      alert(`Error: ${e}`);
+    }
+  };
+
+  // Continues the latest unfinished job of the selected file from its journal.
+  const resumeLastJob = async () => {
+    const jobs = await invoke<JobRecord[]>("list_jobs");
+    const job = jobs.find(j => !j.finished && j.files.some(f => inputFiles().includes(f.source_file)));
+    if (!job) {
+      alert("No unfinished job for this file.");
+      return;
+    }
+    await startTranslation(job.job_id);
+  };
+
+  // Exports the lowest-scoring lines of the latest job of the selected file for human review.
+  const exportReviewQueue = async () => {
+    const jobs = await invoke<JobRecord[]>("list_jobs");
+    const job = jobs.find(j => j.files.some(f => inputFiles().includes(f.source_file)));
+    if (!job) {
+      alert("No job for this file.");
+      return;
+    }
+    const path = await save({
+      defaultPath: `review_${job.job_id}.csv`,
+      filters: [{ name: "CSV", extensions: ["csv"] }],
+    });
+    if (!path) return;
+    try {
+      const count = await invoke<number>("export_review_queue", { jobId: job.job_id, outputPath: path, limit: null });
+      alert(`${count} lines exported for review.`);
+    } catch (e) {
+      alert(`Error: ${e}`);
     }
   };
 
@@ -173,6 +477,16 @@ export default function Translator() {
     await invoke("stop_translation");
   };
 
+  const togglePause = async () => {
+    if (isPaused()) {
+      await invoke("resume_translation");
+      setIsPaused(false);
+    } else {
+      await invoke("pause_translation");
+      setIsPaused(true);
+    }
+  };
+
   const filteredModels = () => {
     const query = config().model.toLowerCase();
     return models().filter(m => m.toLowerCase().includes(query));
@@ -280,7 +594,11 @@ export default function Translator() {
               type="number"
               class="w-full bg-gray-700 border border-gray-600 rounded p-1 text-center"
               value={config().threads}
-              onInput={(e) => setConfig({ ...config(), threads: parseInt(e.currentTarget.value) })}
+              onInput={(e) => {
+                const threads = parseInt(e.currentTarget.value);
+                setConfig({ ...config(), threads });
+                if (isRunning() && threads > 0) invoke("adjust_translation", { threads });
+              }}
             />
           </div>
           <div class="text-center">
@@ -298,7 +616,11 @@ export default function Translator() {
               type="number" step="0.1"
               class="w-full bg-gray-700 border border-gray-600 rounded p-1 text-center"
               value={config().delay}
-              onInput={(e) => setConfig({ ...config(), delay: parseFloat(e.currentTarget.value) })}
+              onInput={(e) => {
+                const delay = parseFloat(e.currentTarget.value);
+                setConfig({ ...config(), delay });
+                if (isRunning() && delay >= 0) invoke("adjust_translation", { delay });
+              }}
             />
           </div>
         </div>
@@ -307,8 +629,9 @@ export default function Translator() {
           <label class="w-16 text-sm font-medium text-gray-400">File:</label>
           <div class="flex-1 flex gap-2">
             <input
-              readOnly
               value={config().last_file}
+              placeholder="File(s), folder or glob (e.g. C:\game\text\*.txt), separated by ;"
+              onInput={(e) => setConfig({ ...config(), last_file: e.currentTarget.value })}
               class="flex-1 bg-gray-700 border border-gray-600 rounded p-2 text-gray-300"
             />
             <button
@@ -320,11 +643,31 @@ export default function Translator() {
           </div>
         </div>
 
+        <div class="flex items-center gap-4">
+          <label class="w-16 text-sm font-medium text-gray-400">Output:</label>
+          <div class="flex-1 flex gap-2">
+            <input
+              value={isSingleOutput() ? outputPath() : ""}
+              disabled={!isSingleOutput()}
+              placeholder="Next to the input file (e.g. goc.vi.txt)"
+              onInput={(e) => setOutputPath(e.currentTarget.value)}
+              class="flex-1 bg-gray-700 border border-gray-600 rounded p-2 text-gray-300 disabled:opacity-50"
+            />
+            <button
+              onClick={handleOutputSelect}
+              disabled={!isSingleOutput()}
+              class="px-4 bg-gray-600 hover:bg-gray-500 rounded font-bold disabled:opacity-50"
+            >
+              💾
+            </button>
+          </div>
+        </div>
+
       </div>
 
       <div class="flex gap-4">
         <button
-          onClick={startTranslation}
+          onClick={() => startTranslation()}
           disabled={isRunning()}
           class={`flex-1 py-3 rounded font-bold text-lg shadow-lg transition-transform active:scale-95 ${isRunning()
             ? "bg-gray-600 cursor-not-allowed text-gray-400"
@@ -333,6 +676,38 @@ export default function Translator() {
         >
           START TRANSLATING
         </button>
+        <button
+          onClick={resumeLastJob}
+          disabled={isRunning()}
+          title="Continue the last unfinished job of this file"
+          class={`px-6 py-3 rounded font-bold text-lg shadow-lg transition-transform active:scale-95 ${isRunning()
+            ? "bg-gray-800 cursor-not-allowed text-gray-600 border border-gray-700"
+            : "bg-blue-600 hover:bg-blue-700 text-white"
+            }`}
+        >
+          ↻
+        </button>
+        <button
+          onClick={exportReviewQueue}
+          disabled={isRunning()}
+          title="Export the lowest-scoring lines of the last job of this file to CSV"
+          class={`px-6 py-3 rounded font-bold text-lg shadow-lg transition-transform active:scale-95 ${isRunning()
+            ? "bg-gray-800 cursor-not-allowed text-gray-600 border border-gray-700"
+            : "bg-gray-600 hover:bg-gray-500 text-white"
+            }`}
+        >
+          ⚑
+        </button>
+        <button
+          onClick={togglePause}
+          disabled={!isRunning()}
+          class={`px-6 py-3 rounded font-bold text-lg shadow-lg transition-transform active:scale-95 ${!isRunning()
+            ? "bg-gray-800 cursor-not-allowed text-gray-600 border border-gray-700"
+            : "bg-yellow-600 hover:bg-yellow-700 text-white"
+            }`}
+        >
+          {isPaused() ? "RESUME" : "PAUSE"}
+        </button>
         <button
           onClick={stopTranslation}
           disabled={!isRunning()}
@@ -345,9 +720,121 @@ export default function Translator() {
         </button>
       </div>
 
+      <div class="bg-gray-800 rounded-xl shadow-lg p-3 space-y-2">
+        <div class="flex items-center gap-2">
+          <h3 class="flex-1 text-xs font-bold text-gray-400 uppercase tracking-wider">Patch Update</h3>
+          <button
+            onClick={preparePatch}
+            disabled={isRunning() || !patchFiles().old_source || !patchFiles().old_translation || !patchFiles().new_source}
+            class="px-3 py-1 text-xs rounded font-bold bg-gray-600 hover:bg-gray-500 disabled:opacity-40"
+          >
+            DIFF
+          </button>
+          <button
+            onClick={runPatch}
+            disabled={isRunning() || !patchReport()}
+            class="px-3 py-1 text-xs rounded font-bold bg-blue-600 hover:bg-blue-700 disabled:bg-gray-700 disabled:text-gray-500"
+          >
+            ▶ TRANSLATE CHANGES
+          </button>
+        </div>
+        <div class="grid grid-cols-3 gap-2">
+          <For each={[["old_source", "Old source"], ["old_translation", "Old translation"], ["new_source", "New source"]] as const}>
+            {([key, label]) => (
+              <button
+                onClick={() => pickPatchFile(key)}
+                title={patchFiles()[key]}
+                class="truncate text-xs text-left bg-gray-700 hover:bg-gray-600 rounded px-2 py-1 font-mono text-gray-300"
+              >
+                {patchFiles()[key] ? patchFiles()[key].split(/[\\/]/).pop() : `📂 ${label}`}
+              </button>
+            )}
+          </For>
+        </div>
+        <Show when={patchReport()}>
+          {(report) => (
+            <div class="text-xs text-gray-400" title={report().output_file}>
+              {report().unchanged} unchanged · {report().modified.length} modified · {report().new.length} new ·{" "}
+              {report().removed.length} removed
+              {report().untranslated.length ? ` · ${report().untranslated.length} untranslated` : ""}
+            </div>
+          )}
+        </Show>
+      </div>
+
+      <div class="bg-gray-800 rounded-xl shadow-lg p-3 space-y-2">
+        <div class="flex items-center gap-2">
+          <h3 class="flex-1 text-xs font-bold text-gray-400 uppercase tracking-wider">Queue</h3>
+          <button
+            onClick={enqueueJob}
+            class="px-3 py-1 text-xs rounded font-bold bg-gray-600 hover:bg-gray-500"
+          >
+            + ADD CURRENT
+          </button>
+          <button
+            onClick={runQueue}
+            disabled={isRunning() || !jobQueue().some(j => j.status.state === "pending" || j.status.state === "stopped")}
+            class="px-3 py-1 text-xs rounded font-bold bg-blue-600 hover:bg-blue-700 disabled:bg-gray-700 disabled:text-gray-500"
+          >
+            ▶ RUN QUEUE
+          </button>
+        </div>
+        <For each={jobQueue()}>
+          {(job) => (
+            <div class="flex items-center gap-2 text-xs bg-gray-700/50 rounded px-2 py-1" title={job.status.detail ?? ""}>
+              <span class={`w-16 font-bold uppercase ${{
+                pending: "text-gray-400",
+                running: "text-blue-400",
+                done: "text-green-400",
+                stopped: "text-yellow-400",
+                failed: "text-red-400",
+              }[job.status.state]}`}>
+                {job.status.state}
+              </span>
+              <span class="flex-1 truncate font-mono text-gray-300">
+                {job.file_paths.map(f => f.split(/[\\/]/).pop()).join(", ")}
+              </span>
+              <span class="text-gray-400">
+                → {job.config.targets?.length ? job.config.targets.map(t => t.target_lang).join(", ") : job.config.target_lang ?? "?"}
+              </span>
+              <button
+                onClick={() => removeQueuedJob(job.id)}
+                disabled={job.status.state === "running"}
+                class="w-6 h-6 rounded bg-gray-600 hover:bg-red-600 disabled:opacity-40"
+              >
+                ✕
+              </button>
+            </div>
+          )}
+        </For>
+      </div>
+
       <div class="flex-1 bg-gray-800 rounded-xl shadow-lg p-4 overflow-hidden flex flex-col">
-        <h3 class="text-xs font-bold text-gray-400 uppercase tracking-wider mb-2">Thread Progress</h3>
+        <div class="flex justify-between items-center mb-2">
+          <h3 class="text-xs font-bold text-gray-400 uppercase tracking-wider">Thread Progress</h3>
+          <Show when={jobUsage()}>
+            <span class="text-xs font-mono text-gray-400">
+              {jobUsage()!.prompt_tokens} in / {jobUsage()!.completion_tokens} out · ${jobUsage()!.cost.toFixed(4)}
+            </span>
+          </Show>
+        </div>
         <div class="flex-1 overflow-y-auto space-y-2 pr-2 custom-scrollbar">
+          <Show when={Object.keys(fileProgress()).length > 1}>
+            <div class="bg-gray-900/50 p-2 rounded space-y-1">
+              <For each={Object.values(fileProgress())}>
+                {(f) => (
+                  <div class="flex items-center gap-3 text-xs" title={f.output_path}>
+                    <div class="w-40 truncate font-mono text-gray-300">{f.path.split(/[\\/]/).pop()}</div>
+                    <div class="w-20 truncate text-gray-400">{f.target_lang}</div>
+                    <div class="flex-1 h-2 bg-gray-900 rounded-full overflow-hidden">
+                      <div class="h-full bg-purple-500 transition-all duration-300" style={{ width: `${f.total > 0 ? (f.done / f.total) * 100 : 100}%` }} />
+                    </div>
+                    <div class="w-20 text-right text-gray-400">{f.done}/{f.total}</div>
+                  </div>
+                )}
+              </For>
+            </div>
+          </Show>
           <For each={Object.keys(progress())}>
             {(threadIdKey) => {
               const threadId = parseInt(threadIdKey);
@@ -358,6 +845,9 @@ export default function Translator() {
                 <div class="bg-gray-700/50 p-3 rounded flex items-center gap-3">
                   <div class={`w-24 text-xs font-mono flex-none ${threadId === 0 ? "text-green-400 font-bold" : "text-gray-400"}`}>
                     {threadId === 0 ? "Progress" : `Thread ${threadId}`}
+                    <Show when={threadId === 0 && p().concurrency}>
+                      <div class="text-[10px] text-gray-500 font-normal">{p().concurrency} workers</div>
+                    </Show>
                   </div>
                   <div class="flex-1 h-3 bg-gray-900 rounded-full overflow-hidden relative">
                     <div
@@ -398,6 +888,118 @@ export default function Translator() {
             </div>
 
             <div class="p-6 overflow-y-auto space-y-4 custom-scrollbar">
+              <div class="grid grid-cols-2 gap-4">
+                <div>
+                  <label class="block text-sm font-bold mb-1">Source Language</label>
+                  <input
+                    type="text"
+                    class="w-full bg-gray-900 border border-gray-600 rounded p-2 text-sm focus:border-green-500 outline-none"
+                    value={config().source_lang ?? "English"}
+                    onInput={(e) => setConfig({ ...config(), source_lang: e.currentTarget.value })}
+                  />
+                </div>
+                <div>
+                  <label class="block text-sm font-bold mb-1">Target Language</label>
+                  <input
+                    type="text"
+                    class="w-full bg-gray-900 border border-gray-600 rounded p-2 text-sm focus:border-green-500 outline-none"
+                    value={config().target_lang ?? "Vietnamese"}
+                    onInput={(e) => setConfig({ ...config(), target_lang: e.currentTarget.value })}
+                  />
+                </div>
+                <div>
+                  <label class="block text-sm font-bold mb-1">Prompt Preset</label>
+                  <select
+                    class="w-full bg-gray-900 border border-gray-600 rounded p-2 text-sm focus:border-green-500 outline-none"
+                    value={config().prompt_preset ?? ""}
+                    onChange={(e) => selectPreset(e.currentTarget.value)}
+                  >
+                    <option value="">(System Prompt below)</option>
+                    <For each={presets()}>
+                      {(p) => (
+                        <option value={p.name} title={p.description ?? ""}>
+                          {p.name}{p.source_lang && p.target_lang ? ` (${p.source_lang} → ${p.target_lang})` : ""}
+                        </option>
+                      )}
+                    </For>
+                  </select>
+                </div>
+                <div>
+                  <label class="block text-sm font-bold mb-1">User Template</label>
+                  <select
+                    class="w-full bg-gray-900 border border-gray-600 rounded p-2 text-sm focus:border-green-500 outline-none"
+                    value={config().user_template ?? ""}
+                    onChange={(e) => setConfig({ ...config(), user_template: e.currentTarget.value })}
+                  >
+                    <option value="">(Lines + format reminder)</option>
+                    <For each={templates()}>{(t) => <option value={t}>{t}</option>}</For>
+                  </select>
+                </div>
+              </div>
+
+              <div>
+                <label class="block text-sm font-bold mb-1">Glossary</label>
+                <div class="flex gap-2">
+                  <input
+                    type="text"
+                    placeholder="term:::translation file"
+                    class="flex-1 bg-gray-900 border border-gray-600 rounded p-2 text-xs focus:border-green-500 outline-none"
+                    value={config().glossary_path ?? ""}
+                    onInput={(e) => setConfig({ ...config(), glossary_path: e.currentTarget.value })}
+                  />
+                  <button onClick={handleGlossarySelect} class="px-3 bg-gray-600 hover:bg-gray-500 rounded">📂</button>
+                </div>
+              </div>
+
+              <div>
+                <div class="flex items-center mb-1">
+                  <label class="flex-1 text-sm font-bold">Target Languages</label>
+                  <button
+                    onClick={() => setConfig({ ...config(), targets: [...(config().targets ?? []), { target_lang: "" }] })}
+                    class="px-2 text-xs bg-gray-600 hover:bg-gray-500 rounded"
+                  >
+                    + ADD
+                  </button>
+                </div>
+                <p class="text-xs text-gray-500 mb-2">
+                  One output per language from the same batches. Empty = Target Language above; empty preset/glossary = the ones above.
+                </p>
+                <For each={config().targets ?? []}>
+                  {(t, i) => (
+                    <div class="flex gap-2 mb-1">
+                      <input
+                        type="text"
+                        placeholder="Language"
+                        class="w-32 bg-gray-900 border border-gray-600 rounded p-1 text-xs focus:border-green-500 outline-none"
+                        value={t.target_lang}
+                        onInput={(e) => setTarget(i(), { target_lang: e.currentTarget.value })}
+                      />
+                      <select
+                        class="w-40 bg-gray-900 border border-gray-600 rounded p-1 text-xs focus:border-green-500 outline-none"
+                        value={t.prompt_preset ?? ""}
+                        onChange={(e) => setTarget(i(), { prompt_preset: e.currentTarget.value })}
+                      >
+                        <option value="">(Same prompt)</option>
+                        <For each={presets()}>{(p) => <option value={p.name}>{p.name}</option>}</For>
+                      </select>
+                      <input
+                        type="text"
+                        placeholder="Glossary file"
+                        class="flex-1 bg-gray-900 border border-gray-600 rounded p-1 text-xs focus:border-green-500 outline-none"
+                        value={t.glossary_path ?? ""}
+                        onInput={(e) => setTarget(i(), { glossary_path: e.currentTarget.value })}
+                      />
+                      <button
+                        onClick={() => setConfig({ ...config(), targets: (config().targets ?? []).filter((_, j) => j !== i()) })}
+                        class="w-6 rounded bg-gray-600 hover:bg-red-600 text-xs"
+                      >
+                        ✕
+                      </button>
+                    </div>
+                  )}
+                </For>
+              </div>
+
               <div>
                 <label class="block text-sm font-bold mb-2">System Prompt</label>
                 <textarea
@@ -434,6 +1036,132 @@ export default function Translator() {
                 />
               </div>
 
+              <div>
+                <label class="block text-sm font-bold mb-2">Output Format</label>
+                <select
+                  class="w-full bg-gray-900 border border-gray-600 rounded p-2 text-sm focus:border-green-500 outline-none"
+                  value={config().response_mode ?? "text"}
+                  onChange={(e) => setConfig({ ...config(), response_mode: e.currentTarget.value as TranslatorConfig["response_mode"] })}
+                >
+                  <option value="text">ID:::Text (any provider)</option>
+                  <option value="json_schema">JSON Schema (response_format)</option>
+                  <option value="json_object">JSON Object (response_format)</option>
+                  <option value="tool">Tool / Function Call</option>
+                </select>
+              </div>
+
+              <div>
+                <label class="block text-sm font-bold mb-2">Batch Grouping</label>
+                <select
+                  class="w-full bg-gray-900 border border-gray-600 rounded p-2 text-sm focus:border-green-500 outline-none"
+                  value={config().segmentation ?? "overlap"}
+                  onChange={(e) => setConfig({ ...config(), segmentation: e.currentTarget.value as TranslatorConfig["segmentation"] })}
+                >
+                  <option value="overlap">Fixed size + word overlap</option>
+                  <option value="id_gap">Consecutive IDs</option>
+                  <option value="scene">Dialogue scenes / UI strings</option>
+                  <option value="tags">Leading tags & markup</option>
+                  <option value="sidecar">Sidecar file (ID:::group)</option>
+                </select>
+                <Show when={config().segmentation === "sidecar"}>
+                  <input
+                    type="text"
+                    placeholder="ID:::group metadata file"
+                    class="w-full mt-2 bg-gray-900 border border-gray-600 rounded p-2 text-xs focus:border-green-500 outline-none"
+                    value={config().segment_metadata_path ?? ""}
+                    onInput={(e) => setConfig({ ...config(), segment_metadata_path: e.currentTarget.value })}
+                  />
+                </Show>
+              </div>
+
+              <label class="block text-sm font-bold mt-2">Network</label>
+              <div class="space-y-2">
+                <input
+                  type="text"
+                  placeholder="Proxy URL (http://host:port, socks5://...)"
+                  class="w-full bg-gray-900 border border-gray-600 rounded p-2 text-xs focus:border-green-500 outline-none"
+                  value={config().proxy_url ?? ""}
+                  onInput={(e) => setConfig({ ...config(), proxy_url: e.currentTarget.value })}
+                />
+                <input
+                  type="text"
+                  placeholder="Extra CA bundle (.pem path)"
+                  class="w-full bg-gray-900 border border-gray-600 rounded p-2 text-xs focus:border-green-500 outline-none"
+                  value={config().ca_bundle_path ?? ""}
+                  onInput={(e) => setConfig({ ...config(), ca_bundle_path: e.currentTarget.value })}
+                />
+                <input
+                  type="text"
+                  placeholder="User-Agent"
+                  class="w-full bg-gray-900 border border-gray-600 rounded p-2 text-xs focus:border-green-500 outline-none"
+                  value={config().user_agent ?? ""}
+                  onInput={(e) => setConfig({ ...config(), user_agent: e.currentTarget.value })}
+                />
+                <textarea
+                  placeholder={"Extra headers, one per line\nHTTP-Referer: https://example.com"}
+                  class="w-full h-16 bg-gray-900 border border-gray-600 rounded p-2 text-xs font-mono focus:border-green-500 outline-none custom-scrollbar"
+                  value={Object.entries(config().extra_headers ?? {}).map(([k, v]) => `${k}: ${v}`).join("\n")}
+                  onChange={(e) => {
+                    const headers: Record<string, string> = {};
+                    for (const line of e.currentTarget.value.split("\n")) {
+                      const idx = line.indexOf(":");
+                      if (idx > 0) headers[line.slice(0, idx).trim()] = line.slice(idx + 1).trim();
+                    }
+                    setConfig({ ...config(), extra_headers: headers });
+                  }}
+                />
+              </div>
+
+              <label class="block text-sm font-bold mt-2">Timeouts (s)</label>
+              <div class="grid grid-cols-3 gap-4">
+                <SettingInput
+                  label="Connect"
+                  value={config().connect_timeout ?? null}
+                  step={0.1}
+                  onChange={(v) => setConfig({ ...config(), connect_timeout: v })}
+                />
+                <SettingInput
+                  label="Request"
+                  value={config().request_timeout ?? null}
+                  step={0.1}
+                  onChange={(v) => setConfig({ ...config(), request_timeout: v })}
+                />
+                <SettingInput
+                  label="Stream Idle"
+                  value={config().stream_idle_timeout ?? null}
+                  step={0.1}
+                  onChange={(v) => setConfig({ ...config(), stream_idle_timeout: v })}
+                />
+              </div>
+
+              <label class="block text-sm font-bold mt-2">Budget</label>
+              <div class="grid grid-cols-2 gap-4">
+                <SettingInput
+                  label="Max Job Cost ($)"
+                  value={config().max_job_cost ?? null}
+                  step={0.1}
+                  onChange={(v) => setConfig({ ...config(), max_job_cost: v })}
+                />
+                <SettingInput
+                  label="Max Job Tokens"
+                  value={config().max_job_tokens ?? null}
+                  step={1}
+                  onChange={(v) => setConfig({ ...config(), max_job_tokens: v })}
+                />
+                <SettingInput
+                  label="Max Daily Cost ($)"
+                  value={config().max_daily_cost ?? null}
+                  step={0.1}
+                  onChange={(v) => setConfig({ ...config(), max_daily_cost: v })}
+                />
+                <SettingInput
+                  label="Max Daily Tokens"
+                  value={config().max_daily_tokens ?? null}
+                  step={1}
+                  onChange={(v) => setConfig({ ...config(), max_daily_tokens: v })}
+                />
+              </div>
+
               <div class="flex items-center gap-2 mt-2">
                 <input
                   type="checkbox"
@@ -444,6 +1172,82 @@ export default function Translator() {
                 />
                 <label for="stream_chk" class="text-sm font-bold">Stream Output (Real-time logs)</label>
               </div>
+
+              <div class="flex items-center gap-2">
+                <input
+                  type="checkbox"
+                  id="adaptive_chk"
+                  class="w-4 h-4 rounded bg-gray-900 border-gray-600 text-green-600 focus:ring-green-500"
+                  checked={config().adaptive_threads ?? false}
+                  onChange={(e) => setConfig({ ...config(), adaptive_threads: e.currentTarget.checked })}
+                />
+                <label for="adaptive_chk" class="text-sm font-bold">Adaptive Threads (back off on 429 / slow responses)</label>
+              </div>
+              <Show when={config().adaptive_threads}>
+                <SettingInput
+                  label="Max Threads"
+                  value={config().max_threads ?? null}
+                  step={1}
+                  onChange={(v) => setConfig({ ...config(), max_threads: v })}
+                />
+              </Show>
+
+              <div class="flex items-center gap-2">
+                <input
+                  type="checkbox"
+                  id="review_chk"
+                  class="w-4 h-4 rounded bg-gray-900 border-gray-600 text-green-600 focus:ring-green-500"
+                  checked={config().review ?? false}
+                  onChange={(e) => setConfig({ ...config(), review: e.currentTarget.checked })}
+                />
+                <label for="review_chk" class="text-sm font-bold">Review Pass (a second model checks each batch)</label>
+              </div>
+              <div class="flex items-center gap-2">
+                <input
+                  type="checkbox"
+                  id="confidence_chk"
+                  class="w-4 h-4 rounded bg-gray-900 border-gray-600 text-green-600 focus:ring-green-500"
+                  checked={config().self_confidence ?? false}
+                  onChange={(e) => setConfig({ ...config(), self_confidence: e.currentTarget.checked })}
+                />
+                <label for="confidence_chk" class="text-sm font-bold">Self-Rated Confidence (model rates each line 1-5)</label>
+              </div>
+              <div>
+                <label class="block text-sm font-bold mb-1">Untranslated Lines</label>
+                <select
+                  class="w-full bg-gray-900 border border-gray-600 rounded p-2 text-sm focus:border-green-500 outline-none"
+                  value={config().leftover_action ?? "flag"}
+                  onChange={(e) => setConfig({ ...config(), leftover_action: e.currentTarget.value as TranslatorConfig["leftover_action"] })}
+                >
+                  <option value="flag">Flag for review</option>
+                  <option value="requeue">Translate again once, then flag</option>
+                </select>
+              </div>
+              <Show when={config().review}>
+                <div class="grid grid-cols-2 gap-4">
+                  <div>
+                    <label class="block text-sm font-bold mb-1">Review Model</label>
+                    <input
+                      type="text"
+                      placeholder="Same as Model"
+                      class="w-full bg-gray-900 border border-gray-600 rounded p-2 text-sm focus:border-green-500 outline-none"
+                      value={config().review_model ?? ""}
+                      onInput={(e) => setConfig({ ...config(), review_model: e.currentTarget.value })}
+                    />
+                  </div>
+                  <div>
+                    <label class="block text-sm font-bold mb-1">Review Prompt</label>
+                    <select
+                      class="w-full bg-gray-900 border border-gray-600 rounded p-2 text-sm focus:border-green-500 outline-none"
+                      value={config().review_template ?? ""}
+                      onChange={(e) => setConfig({ ...config(), review_template: e.currentTarget.value })}
+                    >
+                      <option value="">(Built-in: OK or correction per ID)</option>
+                      <For each={templates()}>{(t) => <option value={t}>{t}</option>}</For>
+                    </select>
+                  </div>
+                </div>
+              </Show>
             </div>
 
             <div class="p-4 border-t border-gray-700 bg-gray-900/50 rounded-b-xl flex justify-end">